@@ -0,0 +1,404 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use planetgen::{
+    coords::CellLocator,
+    export::{
+        gltf::{save_glb, GltfOptions, Ocean},
+        map::render_scalar,
+        mesh::MeshData,
+        stl::{printable_mesh, save_stl, StlOptions},
+        svg::SvgMap,
+        ColorRamp, Interpolation,
+    },
+    generate::{standard_pipeline, Checkpoints, Drift},
+    pipeline::Progress,
+    planet::{GenerationParams, PlanetData},
+    projection::{Equirectangular, Projection},
+    save::{load_planet, save_planet},
+    vector::{contours, plate_boundaries},
+    Result,
+};
+
+// each subdivision quadruples the cells, 10 gives about ten million
+const MAX_SUBDIVISIONS: u32 = 10;
+
+const USAGE: &str = "\
+Usage: planetgen [COMMAND]
+
+Commands:
+  generate [OPTIONS]  Generate a planet without opening a window
+  view                Open the viewer (the default when no command is given)
+  help                Print this message
+
+Generate options:
+  --preset FILE       Read parameters from a toml, json or ron file
+  --seed N            Random seed
+  --subdivisions N    Times the icosahedron is subdivided, at most 10
+  --plates N          Number of tectonic plates
+  --radius R          Planet radius
+  --out FILE          Write the planet to a .glb, .obj, .ply, .stl or .planet
+                      file, may be repeated
+  --map FILE          Write an equirectangular elevation map to a .png or
+                      .svg file, may be repeated
+  --map-width N       Width of maps in pixels (default 2048)
+  --checkpoints DIR   Save the plate drift simulation to DIR as it runs
+  --checkpoint-every N
+                      Drift steps between checkpoints (default 5)
+  --resume FILE       Go on from a checkpoint instead of starting over, with
+                      the parameters it was saved with
+  --timeline DIR      Record the plate drift and write a map of every step
+                      to DIR as numbered .png frames, map-width wide
+";
+
+#[derive(Debug, Default)]
+struct GenerateOptions {
+    params: GenerationParams,
+    outputs: Vec<PathBuf>,
+    maps: Vec<PathBuf>,
+    map_width: u32,
+    checkpoints: Option<PathBuf>,
+    checkpoint_every: u32,
+    resume: Option<PathBuf>,
+    timeline: Option<PathBuf>,
+}
+
+/// Run the command in `args`, which excludes the program name.
+pub fn run(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("generate") => generate_command(&parse_generate(&args[1..])?),
+        Some("help" | "--help" | "-h") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Some("view") | None => bail!("planetgen was built without the viewer feature"),
+        Some(command) => bail!("Unknown command {:?}\n\n{}", command, USAGE),
+    }
+}
+
+fn parse_generate(args: &[String]) -> Result<GenerateOptions> {
+    let mut options = GenerateOptions {
+        map_width: 2048,
+        checkpoint_every: 5,
+        ..Default::default()
+    };
+    // the preset comes first, so flags can override it wherever they are
+    let mut overrides = Vec::new();
+    // flags setting parameters, which a resumed checkpoint already has
+    let mut param_flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => bail!("{} needs a value\n\n{}", flag, USAGE),
+        };
+        let number = || format!("{} expects a number, not {:?}", flag, value);
+        match flag.as_str() {
+            "--preset" => {
+                options.params = load_preset(value)?;
+                param_flags.push(flag.as_str());
+            }
+            "--seed" | "--subdivisions" | "--plates" | "--radius" => {
+                overrides.push((flag.as_str(), value));
+                param_flags.push(flag.as_str());
+            }
+            "--out" => options.outputs.push(value.into()),
+            "--map" => options.maps.push(value.into()),
+            "--map-width" => options.map_width = value.parse().with_context(number)?,
+            "--checkpoints" => options.checkpoints = Some(value.into()),
+            "--checkpoint-every" => {
+                options.checkpoint_every = value.parse().with_context(number)?
+            }
+            "--resume" => options.resume = Some(value.into()),
+            "--timeline" => options.timeline = Some(value.into()),
+            _ => bail!("Unknown option {}\n\n{}", flag, USAGE),
+        }
+    }
+    for (flag, value) in overrides {
+        let number = || format!("{} expects a number, not {:?}", flag, value);
+        let params = &mut options.params;
+        match flag {
+            "--seed" => params.seed = value.parse().with_context(number)?,
+            "--subdivisions" => params.subdivisions = value.parse().with_context(number)?,
+            "--plates" => params.plates = value.parse().with_context(number)?,
+            _ => params.radius = value.parse().with_context(number)?,
+        }
+    }
+    if let (Some(flag), Some(_)) = (param_flags.first(), &options.resume) {
+        bail!(
+            "--resume carries on with the parameters in the checkpoint, so it can't be \
+             combined with {}",
+            flag
+        );
+    }
+    if options.params.radius <= 0.0 {
+        bail!("--radius must be positive");
+    }
+    if options.params.subdivisions > MAX_SUBDIVISIONS {
+        bail!(
+            "--subdivisions can be at most {}, each one makes four times as many cells",
+            MAX_SUBDIVISIONS
+        );
+    }
+    if options.map_width == 0 {
+        bail!("--map-width must be positive");
+    }
+    if options.outputs.is_empty() && options.maps.is_empty() && options.timeline.is_none() {
+        eprintln!("Nothing to write, add --out or --map to keep the planet");
+    }
+    Ok(options)
+}
+
+#[cfg(feature = "serde")]
+fn load_preset(path: &str) -> Result<GenerationParams> {
+    planetgen::config::load_config(path).with_context(|| format!("Reading preset {}", path))
+}
+#[cfg(not(feature = "serde"))]
+fn load_preset(_path: &str) -> Result<GenerationParams> {
+    bail!("planetgen was built without the serde feature, so it can't read presets")
+}
+
+fn generate_command(options: &GenerateOptions) -> Result<()> {
+    let start = Instant::now();
+    let mut pipeline = standard_pipeline();
+    let drift = Drift {
+        record_every: options.timeline.is_some() as u32,
+        checkpoints: options
+            .checkpoints
+            .as_ref()
+            .map(|dir| Checkpoints::new(dir, options.checkpoint_every)),
+        ..Default::default()
+    };
+    pipeline.replace("drift", drift)?;
+    pipeline.on_progress(print_progress);
+    let planet = match &options.resume {
+        Some(path) => {
+            let mut planet =
+                load_planet(path).with_context(|| format!("Reading {}", path.display()))?;
+            eprintln!("Resuming the drift from time {:.3}", planet.time);
+            pipeline.run_from(&mut planet, "drift")?;
+            planet
+        }
+        None => {
+            let mut planet = PlanetData::new(options.params);
+            pipeline.run(&mut planet)?;
+            planet
+        }
+    };
+    eprintln!(
+        "Generated {} cells in {:.1}s",
+        planet.sphere.num_cells(),
+        start.elapsed().as_secs_f32()
+    );
+
+    for path in &options.outputs {
+        write_output(&planet, path).with_context(|| format!("Writing {}", path.display()))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    if let Some(dir) = &options.timeline {
+        let frames = planet
+            .timeline
+            .save_frames(&planet.sphere, dir, options.map_width)?;
+        eprintln!("Wrote {} frames to {}", frames.len(), dir.display());
+    }
+    for path in &options.maps {
+        write_map(&planet, path, options.map_width)
+            .with_context(|| format!("Writing {}", path.display()))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+// redraw the progress bar on the last line of stderr
+fn print_progress(progress: &Progress) {
+    const WIDTH: usize = 30;
+    let filled = ((progress.fraction * WIDTH as f32) as usize).min(WIDTH);
+    let status = match progress.eta {
+        _ if progress.fraction >= 1.0 => "done".to_owned(),
+        Some(eta) => format!("{}, {}s left", progress.stage, eta.as_secs()),
+        None => progress.stage.clone(),
+    };
+    eprint!(
+        "\r[{}{}] {:3.0}% {:<24}",
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        progress.fraction * 100.0,
+        status
+    );
+    if progress.fraction >= 1.0 {
+        eprintln!();
+    }
+}
+
+fn write_output(planet: &PlanetData, path: &Path) -> Result<()> {
+    let sphere = &planet.sphere;
+    let elevation = elevation(planet);
+    let mesh = || {
+        let ramp = terrain_ramp(elevation);
+        let colors: Vec<[f32; 3]> = elevation.iter().map(|&e| ramp.sample(e)).collect();
+        MeshData::from_icosphere(sphere, elevation).with_cell_colors(&colors)
+    };
+    match extension(path).as_str() {
+        "glb" => {
+            let options = GltfOptions {
+                ocean: Some(Ocean::new(sphere.get_radius())),
+                ..Default::default()
+            };
+            save_glb(&mesh(), &options, path)
+        }
+        "obj" => mesh().save_obj(path),
+        "ply" => mesh().save_ply(path),
+        "stl" => save_stl(
+            &printable_mesh(sphere, elevation, &StlOptions::default()),
+            path,
+        ),
+        "planet" => save_planet(planet, path),
+        _ => bail!("Unsupported file type, expected .glb, .obj, .ply, .stl or .planet"),
+    }
+}
+
+fn write_map(planet: &PlanetData, path: &Path, width: u32) -> Result<()> {
+    let sphere = &planet.sphere;
+    let elevation = elevation(planet);
+    let projection = Equirectangular::default();
+    match extension(path).as_str() {
+        "png" => {
+            let locator = CellLocator::new(sphere);
+            let height = projection.height_for_width(width);
+            let map = render_scalar(
+                &locator,
+                elevation,
+                &terrain_ramp(elevation),
+                &projection,
+                width,
+                height,
+                Interpolation::Barycentric,
+            );
+            map.save(path)?;
+            Ok(())
+        }
+        "svg" => {
+            let mut svg = SvgMap::new(&projection, width);
+            svg.add_land(sphere, elevation, 0.0, [0.8, 0.75, 0.6], [0.6, 0.75, 0.9]);
+            svg.add_coastlines(&contours(sphere, elevation, 0.0));
+            if let Some(ids) = planet.get_ids("plate") {
+                let boundaries = plate_boundaries(sphere, ids, planet.get_vectors("velocity"));
+                svg.add_plate_boundaries(&boundaries);
+            }
+            svg.add_graticule(30.0);
+            svg.save(path)
+        }
+        _ => bail!("Unsupported map type, expected .png or .svg"),
+    }
+}
+
+fn elevation(planet: &PlanetData) -> &[f32] {
+    planet
+        .get_scalar("elevation")
+        .expect("generated planets have an elevation")
+}
+
+fn terrain_ramp(elevation: &[f32]) -> ColorRamp {
+    let (min, max) = elevation
+        .iter()
+        .fold((0.0f32, 0.0f32), |(lo, hi), &e| (lo.min(e), hi.max(e)));
+    ColorRamp::terrain(min, 0.0, max)
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<GenerateOptions> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_owned).collect();
+        parse_generate(&args)
+    }
+
+    #[test]
+    fn defaults_without_flags() {
+        let options = parse("").unwrap();
+        assert_eq!(options.params, GenerationParams::default());
+        assert!(options.outputs.is_empty() && options.maps.is_empty());
+        assert_eq!(options.map_width, 2048);
+        assert_eq!(options.checkpoint_every, 5);
+        assert!(options.checkpoints.is_none());
+        assert!(options.resume.is_none());
+        assert!(options.timeline.is_none());
+    }
+
+    #[test]
+    fn every_flag_is_read() {
+        let options = parse(
+            "--seed 3 --subdivisions 2 --plates 4 --radius 6.5 --out a.glb --out b.stl \
+             --map c.png --map-width 64 --checkpoints drift --checkpoint-every 2 \
+             --timeline frames",
+        )
+        .unwrap();
+        let params = GenerationParams {
+            seed: 3,
+            subdivisions: 2,
+            plates: 4,
+            radius: 6.5,
+        };
+        assert_eq!(options.params, params);
+        assert_eq!(options.outputs, [PathBuf::from("a.glb"), "b.stl".into()]);
+        assert_eq!(options.maps, [PathBuf::from("c.png")]);
+        assert_eq!(options.map_width, 64);
+        assert_eq!(options.checkpoints, Some("drift".into()));
+        assert_eq!(options.checkpoint_every, 2);
+        assert_eq!(options.timeline, Some("frames".into()));
+
+        let options = parse("--resume drift/0005.planet").unwrap();
+        assert_eq!(options.resume, Some("drift/0005.planet".into()));
+    }
+
+    #[test]
+    fn bad_flags_are_errors() {
+        for args in [
+            "--colour red",
+            "--seed",
+            "--out a.glb --map",
+            "--seed three",
+            "--radius -1",
+            "--map-width 0",
+        ] {
+            assert!(parse(args).is_err(), "{}", args);
+        }
+    }
+
+    #[test]
+    fn subdivisions_are_limited() {
+        assert!(parse(&format!("--subdivisions {}", MAX_SUBDIVISIONS)).is_ok());
+        assert!(parse(&format!("--subdivisions {}", MAX_SUBDIVISIONS + 1)).is_err());
+    }
+
+    #[test]
+    fn resuming_keeps_the_saved_parameters() {
+        for flag in ["--seed", "--subdivisions"] {
+            let error = parse(&format!("--resume drift/0005.planet {} 3", flag)).unwrap_err();
+            assert!(error.to_string().contains(flag), "{}", error);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn presets_are_overridden_and_not_resumed() {
+        let preset = concat!(env!("CARGO_MANIFEST_DIR"), "/presets/small.ron");
+        let options = parse(&format!("--seed 3 --preset {}", preset)).unwrap();
+        assert_eq!(options.params.seed, 3);
+        assert_eq!(options.params.subdivisions, 4);
+
+        let error = parse(&format!("--preset {} --resume drift/0005.planet", preset)).unwrap_err();
+        assert!(error.to_string().contains("--preset"), "{}", error);
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs, path::Path};
+
+use anyhow::bail;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::Result;
+
+/// Text formats settings such as generation presets can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Ron,
+}
+impl ConfigFormat {
+    /// Format matching the extension of `path`.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("toml") => Ok(ConfigFormat::Toml),
+            Some("json") => Ok(ConfigFormat::Json),
+            Some("ron") => Ok(ConfigFormat::Ron),
+            _ => bail!(
+                "Can't tell the format of {} from its extension, expected .toml, .json or .ron",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, text: &str) -> Result<T> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(text)?,
+            ConfigFormat::Json => serde_json::from_str(text)?,
+            ConfigFormat::Ron => ron::from_str(text)?,
+        })
+    }
+
+    pub fn to_string<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::to_string_pretty(value)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(value)?,
+            ConfigFormat::Ron => ron::ser::to_string_pretty(value, Default::default())?,
+        })
+    }
+}
+
+/// Read settings, such as a [`crate::planet::GenerationParams`] preset, from
+/// a toml, json or ron file depending on its extension.
+pub fn load_config<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+    let path = path.as_ref();
+    let format = ConfigFormat::from_path(path)?;
+    format.parse(&fs::read_to_string(path)?)
+}
+
+pub fn save_config<T: Serialize>(value: &T, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let text = ConfigFormat::from_path(path)?.to_string(value)?;
+    fs::write(path, text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planet::GenerationParams;
+
+    fn preset(name: &str) -> GenerationParams {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("presets")
+            .join(name);
+        load_config(&path).unwrap()
+    }
+
+    #[test]
+    fn shipped_presets_load() {
+        let params = |seed, radius, subdivisions, plates| GenerationParams {
+            seed,
+            radius,
+            subdivisions,
+            plates,
+        };
+        assert_eq!(preset("earthlike.toml"), params(1, 6371.0, 7, 12));
+        assert_eq!(preset("detailed.json"), params(42, 6371.0, 9, 20));
+        assert_eq!(preset("small.ron"), params(7, 1.0, 4, 6));
+    }
+
+    #[test]
+    fn saved_configs_read_back() {
+        let dir = std::env::temp_dir().join(format!("planetgen-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let params = GenerationParams {
+            seed: 1 << 40,
+            radius: 0.125,
+            subdivisions: 3,
+            plates: 9,
+        };
+        for name in ["params.toml", "params.json", "params.RON"] {
+            let path = dir.join(name);
+            save_config(&params, &path).unwrap();
+            assert_eq!(load_config::<GenerationParams>(&path).unwrap(), params);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_extensions_are_errors() {
+        for name in ["params.yaml", "params"] {
+            assert!(ConfigFormat::from_path(Path::new(name)).is_err());
+            assert!(save_config(&GenerationParams::default(), name).is_err());
+        }
+    }
+}
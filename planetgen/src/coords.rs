@@ -0,0 +1,326 @@
+use glam::Vec3;
+
+use crate::icosphere::Icosphere;
+
+/// A position on the surface of the planet in radians.
+///
+/// Latitude is measured from the equator towards +z and longitude
+/// counter-clockwise around +z starting at +x, matching the orientation
+/// of the icosphere.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatLon {
+    pub lat: f32,
+    pub lon: f32,
+}
+impl LatLon {
+    pub fn new(lat: f32, lon: f32) -> Self {
+        LatLon { lat, lon }
+    }
+    pub fn from_degrees(lat: f32, lon: f32) -> Self {
+        LatLon::new(lat.to_radians(), lon.to_radians())
+    }
+    /// Returns `(lat, lon)` in degrees.
+    pub fn to_degrees(self) -> (f32, f32) {
+        (self.lat.to_degrees(), self.lon.to_degrees())
+    }
+    /// Latitude and longitude of the direction of `pos` from the planet centre.
+    pub fn from_vec3(pos: Vec3) -> Self {
+        let pos = pos.normalize();
+        LatLon {
+            lat: libm::asinf(pos.z.clamp(-1.0, 1.0)),
+            lon: libm::atan2f(pos.y, pos.x),
+        }
+    }
+    /// Unit vector pointing from the planet centre towards this position.
+    pub fn to_unit_vec3(self) -> Vec3 {
+        let (sin_lat, cos_lat) = self.lat.sin_cos();
+        let (sin_lon, cos_lon) = self.lon.sin_cos();
+        Vec3::new(cos_lat * cos_lon, cos_lat * sin_lon, sin_lat)
+    }
+    pub fn to_vec3(self, radius: f32) -> Vec3 {
+        self.to_unit_vec3() * radius
+    }
+    /// Angle in radians between two positions as seen from the planet centre.
+    pub fn angle_to(self, other: LatLon) -> f32 {
+        // haversine formula, which stays accurate for nearby points
+        let d_lat = other.lat - self.lat;
+        let d_lon = other.lon - self.lon;
+        let a = (d_lat * 0.5).sin().powi(2)
+            + self.lat.cos() * other.lat.cos() * (d_lon * 0.5).sin().powi(2);
+        2.0 * a.sqrt().min(1.0).asin()
+    }
+    /// Great-circle distance to `other` on a sphere of the given radius.
+    pub fn distance_to(self, other: LatLon, radius: f32) -> f32 {
+        self.angle_to(other) * radius
+    }
+    /// Wrap longitude into `[-pi, pi)` and clamp latitude to `[-pi/2, pi/2]`.
+    pub fn normalized(self) -> Self {
+        use std::f32::consts::{FRAC_PI_2, PI, TAU};
+        LatLon {
+            lat: self.lat.clamp(-FRAC_PI_2, FRAC_PI_2),
+            lon: (self.lon + PI).rem_euclid(TAU) - PI,
+        }
+    }
+}
+
+/// Spherical coordinates of a point relative to the planet centre.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spherical {
+    pub radius: f32,
+    pub lat_lon: LatLon,
+}
+impl Spherical {
+    pub fn from_vec3(pos: Vec3) -> Self {
+        Spherical {
+            radius: pos.length(),
+            lat_lon: LatLon::from_vec3(pos),
+        }
+    }
+    pub fn to_vec3(self) -> Vec3 {
+        self.lat_lon.to_vec3(self.radius)
+    }
+}
+
+/// Angle in radians between the directions of `a` and `b` from the planet centre.
+pub fn angle_between(a: Vec3, b: Vec3) -> f32 {
+    // atan2 of the cross and dot products is well conditioned at every angle
+    libm::atan2f(a.cross(b).length(), a.dot(b))
+}
+
+/// Great-circle distance between the directions of `a` and `b` on a sphere
+/// of the given radius.
+pub fn great_circle_distance(a: Vec3, b: Vec3, radius: f32) -> f32 {
+    angle_between(a, b) * radius
+}
+
+/// Finds the cell containing a point on an [`Icosphere`].
+///
+/// Each cell owns the region of the sphere closer to its centre than to any
+/// other, so the query is a nearest-neighbour search. A coarse grid over the
+/// six faces of the enclosing cube gives a starting cell close to the answer,
+/// then we walk across the mesh towards the point.
+pub struct CellLocator<'a> {
+    sphere: &'a Icosphere,
+    resolution: usize,
+    // starting cell for each grid bucket
+    seeds: Vec<usize>,
+}
+impl<'a> CellLocator<'a> {
+    pub fn new(sphere: &'a Icosphere) -> Self {
+        // aim for roughly one cell per bucket
+        let resolution = ((sphere.num_cells() as f32 / 6.0).sqrt() as usize).max(1);
+        let mut locator = CellLocator {
+            sphere,
+            resolution,
+            seeds: Vec::with_capacity(6 * resolution * resolution),
+        };
+
+        // seed each bucket with the cell nearest its centre, starting each
+        // walk from the previous bucket since they are usually adjacent
+        let mut start = 0;
+        for face in 0..6 {
+            for j in 0..resolution {
+                for i in 0..resolution {
+                    let u = (i as f32 + 0.5) / resolution as f32 * 2.0 - 1.0;
+                    let v = (j as f32 + 0.5) / resolution as f32 * 2.0 - 1.0;
+                    start = locator.walk(start, cube_to_dir(face, u, v));
+                    locator.seeds.push(start);
+                }
+            }
+        }
+        locator
+    }
+
+    pub fn get_sphere(&self) -> &'a Icosphere {
+        self.sphere
+    }
+
+    /// Id of the cell containing the direction of `pos` from the planet centre.
+    pub fn locate(&self, pos: Vec3) -> usize {
+        let dir = pos.normalize();
+        let (face, u, v) = dir_to_cube(dir);
+        let bucket =
+            |t: f32| (((t + 1.0) * 0.5 * self.resolution as f32) as usize).min(self.resolution - 1);
+        let seed = self.seeds[(face * self.resolution + bucket(v)) * self.resolution + bucket(u)];
+        self.walk(seed, dir)
+    }
+
+    pub fn locate_lat_lon(&self, lat_lon: LatLon) -> usize {
+        self.locate(lat_lon.to_unit_vec3())
+    }
+
+    pub fn get_lat_lon(&self, cell: usize) -> LatLon {
+        LatLon::from_vec3(self.sphere.get_position(cell))
+    }
+
+    /// Corners of the mesh triangle hit by the ray from the planet centre
+    /// through `pos`, along with the barycentric weight of each corner.
+    pub fn locate_triangle(&self, pos: Vec3) -> ([usize; 3], [f32; 3]) {
+        let dir = pos.normalize();
+        let nearest = self.locate(dir);
+        // the hit triangle almost always touches the nearest cell, but check
+        // the surrounding ring as well in case it doesn't
+        std::iter::once(nearest)
+            .chain(self.sphere.get_neighbors(nearest).iter().copied())
+            .find_map(|cell| self.triangle_around(cell, dir))
+            .unwrap_or(([nearest; 3], [1.0, 0.0, 0.0]))
+    }
+
+    // check each triangle in the fan around `cell` for an intersection
+    fn triangle_around(&self, cell: usize, dir: Vec3) -> Option<([usize; 3], [f32; 3])> {
+        let ring = self.sphere.get_neighbors(cell);
+        (0..ring.len()).find_map(|i| {
+            let corners = [cell, ring[i], ring[(i + 1) % ring.len()]];
+            let [a, b, c] = corners.map(|k| self.sphere.get_position(k));
+            let normal = (b - a).cross(c - a);
+            let facing = normal.dot(dir);
+            if facing <= 0.0 {
+                return None;
+            }
+            // intersect the ray with the plane of the triangle
+            let p = dir * (normal.dot(a) / facing);
+            let area = normal.length_squared();
+            let weights = [
+                (b - p).cross(c - p).dot(normal) / area,
+                (c - p).cross(a - p).dot(normal) / area,
+                (a - p).cross(b - p).dot(normal) / area,
+            ];
+            if weights.iter().all(|&w| w >= -1e-5) {
+                let weights = weights.map(|w| w.max(0.0));
+                let sum: f32 = weights.iter().sum();
+                Some((corners, weights.map(|w| w / sum)))
+            } else {
+                None
+            }
+        })
+    }
+
+    // greedy walk to the cell whose centre is closest to `dir`, which always
+    // terminates at the nearest cell on a delaunay triangulation like ours
+    fn walk(&self, start: usize, dir: Vec3) -> usize {
+        let closeness = |cell: usize| self.sphere.get_position(cell).normalize().dot(dir);
+        let mut current = start;
+        let mut best = closeness(current);
+        loop {
+            let next = self
+                .sphere
+                .get_neighbors(current)
+                .iter()
+                .map(|&n| (n, closeness(n)))
+                .filter(|&(_, c)| c > best)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            match next {
+                Some((cell, c)) => {
+                    current = cell;
+                    best = c;
+                }
+                None => return current,
+            }
+        }
+    }
+}
+
+/// Project a unit direction onto the unit cube, returning the face index
+/// (+x, -x, +y, -y, +z, -z) and coordinates on that face in `[-1, 1]`.
+pub fn dir_to_cube(dir: Vec3) -> (usize, f32, f32) {
+    let abs = dir.abs();
+    if abs.x >= abs.y && abs.x >= abs.z {
+        if dir.x > 0.0 {
+            (0, -dir.z / abs.x, -dir.y / abs.x)
+        } else {
+            (1, dir.z / abs.x, -dir.y / abs.x)
+        }
+    } else if abs.y >= abs.z {
+        if dir.y > 0.0 {
+            (2, dir.x / abs.y, dir.z / abs.y)
+        } else {
+            (3, dir.x / abs.y, -dir.z / abs.y)
+        }
+    } else if dir.z > 0.0 {
+        (4, dir.x / abs.z, -dir.y / abs.z)
+    } else {
+        (5, -dir.x / abs.z, -dir.y / abs.z)
+    }
+}
+
+/// Inverse of [`dir_to_cube`], returning a unit direction.
+pub fn cube_to_dir(face: usize, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        5 => Vec3::new(-u, -v, -1.0),
+        _ => panic!("Invalid cube face {}", face),
+    }
+    .normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_weights(locator: &CellLocator, dir: Vec3) {
+        let (corners, weights) = locator.locate_triangle(dir);
+        let sum: f32 = weights.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-5, "{:?} sums to {}", dir, sum);
+        assert!(
+            weights.iter().all(|&w| w >= 0.0),
+            "{:?}: {:?}",
+            dir,
+            weights
+        );
+        // the weighted corners lie on the ray through `dir`
+        let sphere = locator.get_sphere();
+        let hit: Vec3 = corners
+            .iter()
+            .zip(weights)
+            .fold(Vec3::ZERO, |hit, (&c, w)| hit + sphere.get_position(c) * w);
+        let off_ray = hit.normalize().cross(dir.normalize()).length();
+        assert!(off_ray < 1e-4, "{:?} hits {:?}", dir, hit);
+    }
+
+    #[test]
+    fn cell_centres_locate_to_themselves() {
+        for (radius, subdivisions) in [(1.0, 0), (1.0, 4), (6371.0, 5)] {
+            let sphere = Icosphere::subdivided(radius, subdivisions);
+            let locator = CellLocator::new(&sphere);
+            for cell in 0..sphere.num_cells() {
+                assert_eq!(locator.locate(sphere.get_position(cell)), cell);
+                assert_eq!(locator.locate_lat_lon(locator.get_lat_lon(cell)), cell);
+            }
+        }
+    }
+
+    #[test]
+    fn weights_sum_to_one_near_edges() {
+        let sphere = Icosphere::subdivided(1.0, 3);
+        let locator = CellLocator::new(&sphere);
+        for face in sphere.get_faces() {
+            let [a, b, c] = face.get_corners().map(|k| sphere.get_position(k));
+            let centre = (a + b + c) / 3.0;
+            for (p, q) in [(a, b), (b, c), (c, a)] {
+                let midpoint = (p + q) * 0.5;
+                for t in [0.0, 1e-6, 1e-3] {
+                    assert_weights(&locator, midpoint.lerp(centre, t));
+                }
+                assert_weights(&locator, p.lerp(centre, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn weights_sum_to_one_near_the_poles() {
+        let sphere = Icosphere::subdivided(1.0, 4);
+        let locator = CellLocator::new(&sphere);
+        for lat in [90.0, 89.999, 89.9, -89.9, -89.999, -90.0] {
+            for lon in (-180..180).step_by(15) {
+                let dir = LatLon::from_degrees(lat, lon as f32).to_unit_vec3();
+                assert_weights(&locator, dir);
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use glam::Vec3;
+use image::RgbImage;
+
+use super::{heightmap::Heightmap, to_rgb8, Interpolation};
+use crate::{
+    coords::{self, CellLocator},
+    field::FieldValue,
+};
+
+/// Suffixes of the six faces in the layer order wgpu expects for cube
+/// textures: +x, -x, +y, -y, +z, -z.
+pub const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// Where samples sit on each face of the cube.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TexelLayout {
+    /// Sample the centre of each texel, as a GPU does when sampling a cube
+    /// texture. Neighbouring faces line up once filtered with a cube sampler.
+    Centers,
+    /// Put the first and last rows and columns exactly on the cube edges,
+    /// so neighbouring faces share identical border samples. Suits terrain
+    /// tiles whose edge vertices must match.
+    Corners,
+}
+
+/// Direction from the planet centre through texel `(x, y)` of `face`.
+///
+/// Faces follow the wgpu (and Vulkan/D3D) cube map convention, where the
+/// texel u axis runs along the face's `s` direction and rows run along `t`.
+pub fn texel_direction(face: usize, size: u32, layout: TexelLayout, x: u32, y: u32) -> Vec3 {
+    let coord = |i: u32| match layout {
+        TexelLayout::Centers => (i as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+        TexelLayout::Corners if size > 1 => i as f32 / (size - 1) as f32 * 2.0 - 1.0,
+        TexelLayout::Corners => 0.0,
+    };
+    coords::cube_to_dir(face, coord(x), coord(y))
+}
+
+/// Elevation of every texel of the six faces.
+pub fn elevation_faces(
+    locator: &CellLocator,
+    elevation: &[f32],
+    size: u32,
+    layout: TexelLayout,
+) -> [Heightmap; 6] {
+    assert_eq!(elevation.len(), locator.get_sphere().num_cells());
+    std::array::from_fn(|face| {
+        Heightmap::from_fn(size, size, |x, y| {
+            let dir = texel_direction(face, size, layout, x, y);
+            f32::sample(elevation, locator, dir, Interpolation::Barycentric)
+        })
+    })
+}
+
+/// Object-space normals of the terrain raised by `elevation`, packed into
+/// RGB as `0.5 + 0.5 * n`.
+pub fn normal_faces(
+    locator: &CellLocator,
+    elevation: &[f32],
+    size: u32,
+    layout: TexelLayout,
+) -> [RgbImage; 6] {
+    let normals = locator.get_sphere().get_normals(elevation);
+    std::array::from_fn(|face| {
+        RgbImage::from_fn(size, size, |x, y| {
+            let dir = texel_direction(face, size, layout, x, y);
+            let n = Vec3::sample(&normals, locator, dir, Interpolation::Barycentric).normalize();
+            to_rgb8((n * 0.5 + 0.5).into())
+        })
+    })
+}
+
+/// Surface colour of every texel of the six faces.
+pub fn albedo_faces(
+    locator: &CellLocator,
+    colors: &[[f32; 3]],
+    size: u32,
+    layout: TexelLayout,
+    interpolation: Interpolation,
+) -> [RgbImage; 6] {
+    assert_eq!(colors.len(), locator.get_sphere().num_cells());
+    let colors: Vec<Vec3> = colors.iter().map(|&c| c.into()).collect();
+    std::array::from_fn(|face| {
+        RgbImage::from_fn(size, size, |x, y| {
+            let dir = texel_direction(face, size, layout, x, y);
+            to_rgb8(Vec3::sample(&colors, locator, dir, interpolation).into())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icosphere::Icosphere;
+
+    const AXES: [Vec3; 6] = [
+        Vec3::X,
+        Vec3::NEG_X,
+        Vec3::Y,
+        Vec3::NEG_Y,
+        Vec3::Z,
+        Vec3::NEG_Z,
+    ];
+
+    #[test]
+    fn face_centres_point_along_their_axes() {
+        for layout in [TexelLayout::Centers, TexelLayout::Corners] {
+            for (face, axis) in AXES.into_iter().enumerate() {
+                let dir = texel_direction(face, 5, layout, 2, 2);
+                assert!(
+                    dir.abs_diff_eq(axis, 1e-6),
+                    "{} {:?}",
+                    FACE_NAMES[face],
+                    dir
+                );
+            }
+        }
+        // the first texel of +x is where -z and +y meet, as in wgpu
+        let corner = texel_direction(0, 5, TexelLayout::Corners, 0, 0);
+        assert!(corner.abs_diff_eq(Vec3::ONE.normalize(), 1e-6));
+    }
+
+    #[test]
+    fn corner_layout_shares_edge_texels() {
+        let size = 6;
+        let border = |face: usize| {
+            (0..size).flat_map(move |i| {
+                [(i, 0), (i, size - 1), (0, i), (size - 1, i)]
+                    .map(|(x, y)| texel_direction(face, size, TexelLayout::Corners, x, y))
+            })
+        };
+        for (face, name) in FACE_NAMES.iter().enumerate() {
+            for dir in border(face) {
+                let shared = (0..6)
+                    .filter(|&other| other != face)
+                    .any(|other| border(other).any(|d| d.abs_diff_eq(dir, 1e-6)));
+                assert!(shared, "{} {:?}", name, dir);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_cover_every_direction() {
+        let size = 16;
+        let texels: Vec<Vec3> = (0..6)
+            .flat_map(|face| {
+                (0..size * size).map(move |i| {
+                    texel_direction(face, size, TexelLayout::Centers, i % size, i / size)
+                })
+            })
+            .collect();
+        // no texel centre is further than half a texel diagonal away
+        let max_angle = std::f32::consts::SQRT_2 / size as f32;
+        let sphere = Icosphere::subdivided(1.0, 3);
+        for cell in 0..sphere.num_cells() {
+            let dir = sphere.get_position(cell).normalize();
+            let nearest = texels
+                .iter()
+                .map(|t| t.dot(dir))
+                .fold(f32::NEG_INFINITY, f32::max);
+            assert!(nearest.acos() < max_angle, "{:?}", dir);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use crate::{
+    coords::LatLon,
+    vector::{Boundary, Polyline, River},
+    Result,
+};
+
+/// Value of a feature property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    String(String),
+    Number(f64),
+}
+impl From<&str> for Property {
+    fn from(s: &str) -> Self {
+        Property::String(s.to_owned())
+    }
+}
+impl From<f32> for Property {
+    fn from(n: f32) -> Self {
+        Property::Number(n as f64)
+    }
+}
+impl From<u32> for Property {
+    fn from(n: u32) -> Self {
+        Property::Number(n as f64)
+    }
+}
+
+/// GeoJSON feature collection of lines on the planet, in degrees of
+/// longitude and latitude.
+///
+/// Lines crossing the antimeridian are split there into a MultiLineString,
+/// as RFC 7946 recommends.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureCollection {
+    features: Vec<String>,
+}
+impl FeatureCollection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Add a line with the given properties.
+    pub fn add_line(&mut self, line: &Polyline, properties: &[(&str, Property)]) {
+        let parts = split_antimeridian(line);
+        let coordinates = |part: &Vec<[f32; 2]>| {
+            let points: Vec<String> = part
+                .iter()
+                .map(|[lon, lat]| format!("[{:.5}, {:.5}]", lon, lat))
+                .collect();
+            format!("[{}]", points.join(", "))
+        };
+        let geometry = match parts.as_slice() {
+            [] => return,
+            [part] => format!(
+                "{{\"type\": \"LineString\", \"coordinates\": {}}}",
+                coordinates(part)
+            ),
+            parts => format!(
+                "{{\"type\": \"MultiLineString\", \"coordinates\": [{}]}}",
+                parts.iter().map(coordinates).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        let properties: Vec<String> = properties
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Property::String(s) => quote(s),
+                    // json has no representation for nan or infinity
+                    Property::Number(n) if n.is_finite() => n.to_string(),
+                    Property::Number(_) => "null".to_owned(),
+                };
+                format!("{}: {}", quote(key), value)
+            })
+            .collect();
+        self.features.push(format!(
+            "{{\"type\": \"Feature\", \"properties\": {{{}}}, \"geometry\": {}}}",
+            properties.join(", "),
+            geometry
+        ));
+    }
+
+    /// Add coastlines traced with [`crate::vector::contours`].
+    pub fn add_coastlines(&mut self, coastlines: &[Polyline]) {
+        for line in coastlines {
+            self.add_line(line, &[("kind", "coastline".into())]);
+        }
+    }
+
+    pub fn add_plate_boundaries(&mut self, boundaries: &[Boundary]) {
+        for boundary in boundaries {
+            self.add_line(
+                &boundary.line,
+                &[
+                    ("kind", "plate_boundary".into()),
+                    ("boundary_type", boundary.kind.name().into()),
+                    ("plate_a", boundary.plates.0.into()),
+                    ("plate_b", boundary.plates.1.into()),
+                ],
+            );
+        }
+    }
+
+    pub fn add_rivers(&mut self, rivers: &[River]) {
+        for river in rivers {
+            self.add_line(
+                &river.line,
+                &[
+                    ("kind", "river".into()),
+                    ("order", river.order.into()),
+                    ("flow", river.flow.into()),
+                ],
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"type\": \"FeatureCollection\", \"features\": [\n");
+        for (i, feature) in self.features.iter().enumerate() {
+            let separator = if i + 1 < self.features.len() { "," } else { "" };
+            let _ = writeln!(json, "  {}{}", feature, separator);
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// [lon, lat] degrees of each part of the line, cut where it crosses the
+// antimeridian with a point on either side of the cut
+fn split_antimeridian(line: &Polyline) -> Vec<Vec<[f32; 2]>> {
+    let mut points = line.points.clone();
+    if line.closed && points.len() > 1 {
+        points.push(points[0]);
+    }
+    let degrees = |p| {
+        let (lat, lon) = LatLon::from_vec3(p).to_degrees();
+        [lon, lat]
+    };
+
+    let mut parts = Vec::new();
+    let mut part = Vec::new();
+    for (i, &p) in points.iter().enumerate() {
+        if i > 0 {
+            let q = points[i - 1];
+            // crossing y = 0 on the far side from the prime meridian
+            if (q.y < 0.0) != (p.y < 0.0) {
+                let t = q.y / (q.y - p.y);
+                let cut = q.lerp(p, t);
+                if cut.x < 0.0 {
+                    let lat = degrees(cut)[1];
+                    let side = if q.y < 0.0 { -180.0 } else { 180.0 };
+                    part.push([side, lat]);
+                    parts.push(std::mem::take(&mut part));
+                    part.push([-side, lat]);
+                }
+            }
+        }
+        part.push(degrees(p));
+    }
+    if part.len() > 1 {
+        parts.push(part);
+    }
+    parts.retain(|p| p.len() > 1);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(lat_lons: &[(f32, f32)], closed: bool) -> Polyline {
+        Polyline {
+            points: lat_lons
+                .iter()
+                .map(|&(lat, lon)| LatLon::from_degrees(lat, lon).to_unit_vec3())
+                .collect(),
+            closed,
+        }
+    }
+
+    #[test]
+    fn lines_across_the_antimeridian_are_cut() {
+        let parts = split_antimeridian(&line(&[(10.0, 170.0), (20.0, -170.0)], false));
+        assert_eq!(parts.len(), 2);
+        let (end, start) = (parts[0][parts[0].len() - 1], parts[1][0]);
+        assert_eq!(end[0], 180.0);
+        assert_eq!(start[0], -180.0);
+        assert_eq!(end[1], start[1]);
+        assert!(end[1] > 10.0 && end[1] < 20.0, "{:?}", end);
+        assert!((parts[0][0][0] - 170.0).abs() < 1e-3);
+        assert!((parts[1][1][0] + 170.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lines_elsewhere_stay_whole() {
+        let lat_lons = [(0.0, -10.0), (5.0, 10.0), (5.0, 90.0), (0.0, 170.0)];
+        let parts = split_antimeridian(&line(&lat_lons, false));
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].len(), lat_lons.len());
+    }
+
+    #[test]
+    fn closed_loops_are_cut_once_and_come_back_round() {
+        let lat_lons: Vec<(f32, f32)> = (0..12).map(|i| (60.0, i as f32 * 30.0)).collect();
+        let parts = split_antimeridian(&line(&lat_lons, true));
+        assert_eq!(parts.len(), 2);
+        // every point, the first again to close the loop and the two cuts
+        assert_eq!(parts[0].len() + parts[1].len(), 12 + 1 + 2);
+        assert_eq!(parts[0][parts[0].len() - 1][0], 180.0);
+        assert_eq!(parts[1][0][0], -180.0);
+        assert_eq!(parts[0][0], parts[1][parts[1].len() - 1]);
+    }
+}
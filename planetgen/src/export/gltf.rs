@@ -0,0 +1,338 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Cursor, Write},
+    path::Path,
+};
+
+use anyhow::{bail, ensure};
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+
+use super::mesh::{Attribute, MeshData};
+use crate::{icosphere::Icosphere, Result};
+
+/// Sphere drawn at sea level around the planet.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ocean {
+    pub radius: f32,
+    /// Linear RGBA colour, blended using its alpha.
+    pub color: [f32; 4],
+    pub subdivisions: u32,
+}
+impl Ocean {
+    pub fn new(radius: f32) -> Self {
+        Ocean {
+            radius,
+            color: [0.05, 0.2, 0.5, 0.8],
+            subdivisions: 5,
+        }
+    }
+}
+
+/// Optional extras written alongside the planet mesh.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GltfOptions<'a> {
+    /// Texture sampled with the mesh's texture coordinates.
+    pub albedo: Option<&'a RgbaImage>,
+    pub ocean: Option<Ocean>,
+}
+
+/// Write a self-contained binary glTF 2.0 file of the mesh.
+///
+/// The planet is rotated so its +z axis points along glTF's +y. Extra
+/// attributes are written as custom `_NAME` float attributes, so their
+/// names must be letters, digits and underscores, unique ignoring case.
+pub fn write_glb(mesh: &MeshData, options: &GltfOptions, mut w: impl Write) -> Result<()> {
+    let names = attribute_names(mesh)?;
+    let mut gltf = Builder::default();
+
+    let mut planet = vec![format!(
+        "\"POSITION\": {}",
+        gltf.vec3s(&mesh.positions, true)
+    )];
+    if let Some(normals) = &mesh.normals {
+        planet.push(format!("\"NORMAL\": {}", gltf.vec3s(normals, false)));
+    }
+    if let Some(tex_coords) = &mesh.tex_coords {
+        let data: Vec<f32> = tex_coords.iter().flatten().copied().collect();
+        let accessor = gltf.accessor(&data, 5126, tex_coords.len(), "VEC2", None);
+        planet.push(format!("\"TEXCOORD_0\": {}", accessor));
+    }
+    if let Some(colors) = &mesh.colors {
+        planet.push(format!("\"COLOR_0\": {}", gltf.vec3s(colors, false)));
+    }
+    for (name, (_, values)) in names.iter().zip(&mesh.attributes) {
+        let data: Vec<f32> = match values {
+            Attribute::F32(v) => v.clone(),
+            Attribute::U32(v) => v.iter().map(|&v| v as f32).collect(),
+        };
+        let accessor = gltf.accessor(&data, 5126, data.len(), "SCALAR", None);
+        planet.push(format!("\"{}\": {}", name, accessor));
+    }
+    let indices = gltf.indices(&mesh.indices);
+
+    let base_color = match options.albedo {
+        Some(albedo) => {
+            let view = gltf.image(albedo)?;
+            gltf.images.push(format!(
+                "{{\"bufferView\": {}, \"mimeType\": \"image/png\"}}",
+                view
+            ));
+            // repeat horizontally since texture coordinates run past the antimeridian
+            gltf.samplers
+                .push("{\"wrapS\": 10497, \"wrapT\": 33071}".to_owned());
+            gltf.textures
+                .push("{\"source\": 0, \"sampler\": 0}".to_owned());
+            "\"baseColorTexture\": {\"index\": 0}, "
+        }
+        None => "",
+    };
+    gltf.materials.push(format!(
+        "{{\"name\": \"planet\", \"pbrMetallicRoughness\": {{{}\"metallicFactor\": 0, \"roughnessFactor\": 1}}}}",
+        base_color
+    ));
+    gltf.meshes.push(format!(
+        "{{\"name\": \"planet\", \"primitives\": [{{\"attributes\": {{{}}}, \"indices\": {}, \"material\": 0}}]}}",
+        planet.join(", "),
+        indices
+    ));
+    gltf.nodes.push(node("planet", 0));
+
+    if let Some(ocean) = options.ocean {
+        let sphere = Icosphere::subdivided(ocean.radius, ocean.subdivisions);
+        let elevation = vec![0.0; sphere.num_cells()];
+        let sea = MeshData::from_icosphere(&sphere, &elevation);
+        let position = gltf.vec3s(&sea.positions, true);
+        let normal = gltf.vec3s(sea.normals.as_deref().unwrap_or_default(), false);
+        let indices = gltf.indices(&sea.indices);
+        let [r, g, b, a] = ocean.color;
+        gltf.materials.push(format!(
+            "{{\"name\": \"ocean\", \"alphaMode\": \"BLEND\", \"pbrMetallicRoughness\": {{\"baseColorFactor\": [{}, {}, {}, {}], \"metallicFactor\": 0, \"roughnessFactor\": 0.2}}}}",
+            r, g, b, a
+        ));
+        gltf.meshes.push(format!(
+            "{{\"name\": \"ocean\", \"primitives\": [{{\"attributes\": {{\"POSITION\": {}, \"NORMAL\": {}}}, \"indices\": {}, \"material\": 1}}]}}",
+            position, normal, indices
+        ));
+        gltf.nodes.push(node("ocean", 1));
+    }
+
+    gltf.write(&mut w)
+}
+
+pub fn save_glb(mesh: &MeshData, options: &GltfOptions, path: impl AsRef<Path>) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_glb(mesh, options, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+// custom attribute names for the mesh's attributes, which go into the json
+// unescaped
+fn attribute_names(mesh: &MeshData) -> Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::with_capacity(mesh.attributes.len());
+    for (name, _) in &mesh.attributes {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        ensure!(
+            !name.is_empty() && name.chars().all(valid),
+            "Attribute name {:?} should be letters, digits and underscores",
+            name
+        );
+        let custom = format!("_{}", name.to_ascii_uppercase());
+        if let Some(other) = names.iter().position(|n| *n == custom) {
+            bail!(
+                "Attributes {:?} and {:?} would both be written as {}",
+                mesh.attributes[other].0,
+                name,
+                custom
+            );
+        }
+        names.push(custom);
+    }
+    Ok(names)
+}
+
+// rotate -90 degrees about x to turn our z-up planet into gltf's y-up
+fn node(name: &str, mesh: usize) -> String {
+    format!(
+        "{{\"name\": \"{}\", \"mesh\": {}, \"rotation\": [-0.70710677, 0, 0, 0.70710677]}}",
+        name, mesh
+    )
+}
+
+// accumulates the binary buffer and the json arrays describing it
+#[derive(Default)]
+struct Builder {
+    bin: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+    images: Vec<String>,
+    samplers: Vec<String>,
+    textures: Vec<String>,
+    materials: Vec<String>,
+    meshes: Vec<String>,
+    nodes: Vec<String>,
+}
+impl Builder {
+    fn buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // every view starts 4-byte aligned
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        let target = target
+            .map(|t| format!(", \"target\": {}", t))
+            .unwrap_or_default();
+        self.buffer_views.push(format!(
+            "{{\"buffer\": 0, \"byteOffset\": {}, \"byteLength\": {}{}}}",
+            self.bin.len(),
+            bytes.len(),
+            target
+        ));
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    fn accessor<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        component_type: u32,
+        count: usize,
+        ty: &str,
+        bounds: Option<(String, String)>,
+    ) -> usize {
+        let target = if ty == "SCALAR" && component_type == 5125 {
+            34963
+        } else {
+            34962
+        };
+        let view = self.buffer_view(bytemuck::cast_slice(data), Some(target));
+        let bounds = bounds
+            .map(|(min, max)| format!(", \"min\": [{}], \"max\": [{}]", min, max))
+            .unwrap_or_default();
+        self.accessors.push(format!(
+            "{{\"bufferView\": {}, \"componentType\": {}, \"count\": {}, \"type\": \"{}\"{}}}",
+            view, component_type, count, ty, bounds
+        ));
+        self.accessors.len() - 1
+    }
+
+    fn vec3s(&mut self, data: &[[f32; 3]], with_bounds: bool) -> usize {
+        let bounds = with_bounds.then(|| {
+            let mut min = [f32::INFINITY; 3];
+            let mut max = [f32::NEG_INFINITY; 3];
+            for v in data {
+                for i in 0..3 {
+                    min[i] = min[i].min(v[i]);
+                    max[i] = max[i].max(v[i]);
+                }
+            }
+            let list = |v: [f32; 3]| format!("{}, {}, {}", v[0], v[1], v[2]);
+            (list(min), list(max))
+        });
+        let flat: Vec<f32> = data.iter().flatten().copied().collect();
+        self.accessor(&flat, 5126, data.len(), "VEC3", bounds)
+    }
+
+    fn indices(&mut self, data: &[u32]) -> usize {
+        self.accessor(data, 5125, data.len(), "SCALAR", None)
+    }
+
+    fn image(&mut self, image: &RgbaImage) -> Result<usize> {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(image.clone()).write_to(&mut png, ImageOutputFormat::Png)?;
+        Ok(self.buffer_view(png.get_ref(), None))
+    }
+
+    fn write(mut self, w: &mut impl Write) -> Result<()> {
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        let scene_nodes: Vec<String> = (0..self.nodes.len()).map(|i| i.to_string()).collect();
+        // gltf forbids empty arrays, so leave out unused ones entirely
+        let optional = |name: &str, items: &[String]| {
+            if items.is_empty() {
+                String::new()
+            } else {
+                format!("  \"{}\": [{}],\n", name, items.join(", "))
+            }
+        };
+        let mut json = format!(
+            concat!(
+                "{{\n",
+                "  \"asset\": {{\"version\": \"2.0\", \"generator\": \"planetgen\"}},\n",
+                "  \"scene\": 0,\n",
+                "  \"scenes\": [{{\"nodes\": [{}]}}],\n",
+                "  \"nodes\": [{}],\n",
+                "  \"meshes\": [{}],\n",
+                "  \"materials\": [{}],\n",
+                "{}{}{}",
+                "  \"accessors\": [{}],\n",
+                "  \"bufferViews\": [{}],\n",
+                "  \"buffers\": [{{\"byteLength\": {}}}]\n",
+                "}}"
+            ),
+            scene_nodes.join(", "),
+            self.nodes.join(", "),
+            self.meshes.join(", "),
+            self.materials.join(", "),
+            optional("textures", &self.textures),
+            optional("samplers", &self.samplers),
+            optional("images", &self.images),
+            self.accessors.join(", "),
+            self.buffer_views.join(", "),
+            self.bin.len()
+        )
+        .into_bytes();
+        // the json chunk is padded with spaces
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+
+        let length = 12 + 8 + json.len() + 8 + self.bin.len();
+        w.write_all(b"glTF")?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&(length as u32).to_le_bytes())?;
+        w.write_all(&(json.len() as u32).to_le_bytes())?;
+        w.write_all(b"JSON")?;
+        w.write_all(&json)?;
+        w.write_all(&(self.bin.len() as u32).to_le_bytes())?;
+        w.write_all(b"BIN\0")?;
+        w.write_all(&self.bin)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(names: &[&str]) -> MeshData {
+        let positions = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        names
+            .iter()
+            .fold(MeshData::new(positions, vec![0, 1, 2]), |mesh, name| {
+                mesh.with_attribute(name, Attribute::F32(vec![0.0; 3]))
+            })
+    }
+
+    fn write(mesh: &MeshData) -> Result<Vec<u8>> {
+        let mut glb = Vec::new();
+        write_glb(mesh, &GltfOptions::default(), &mut glb)?;
+        Ok(glb)
+    }
+
+    #[test]
+    fn attributes_are_written_upper_case() {
+        let glb = write(&triangle(&["elevation", "plate_id"])).unwrap();
+        let json = String::from_utf8_lossy(&glb);
+        assert!(json.contains("\"_ELEVATION\": "));
+        assert!(json.contains("\"_PLATE_ID\": "));
+    }
+
+    #[test]
+    fn attribute_names_must_be_plain_and_unique() {
+        for names in [&["a\"b"][..], &[""], &["sea level"], &["a", "A"]] {
+            assert!(write(&triangle(names)).is_err(), "{:?}", names);
+        }
+    }
+}
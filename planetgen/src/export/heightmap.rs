@@ -0,0 +1,177 @@
+use std::{fs, path::Path};
+
+use image::{ImageBuffer, Luma};
+
+use super::{map::pixel_to_plane, Interpolation};
+use crate::{coords::CellLocator, field::FieldValue, projection::Projection, Result};
+
+/// Row-major grid of elevation samples.
+///
+/// Samples outside the projected map of [`Heightmap::from_projection`] are
+/// NaN and are ignored by [`Heightmap::range`].
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f32>,
+}
+impl Heightmap {
+    pub fn from_fn(width: u32, height: u32, mut f: impl FnMut(u32, u32) -> f32) -> Self {
+        let mut data = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+        Heightmap {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Sample a per-cell elevation field over a map in the given projection.
+    pub fn from_projection(
+        locator: &CellLocator,
+        elevation: &[f32],
+        projection: &impl Projection,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        assert_eq!(elevation.len(), locator.get_sphere().num_cells());
+        Heightmap::from_fn(width, height, |x, y| {
+            let point = pixel_to_plane(projection, width, height, x as f32 + 0.5, y as f32 + 0.5);
+            match projection.inverse(point) {
+                Some(lat_lon) => f32::sample(
+                    elevation,
+                    locator,
+                    lat_lon.to_unit_vec3(),
+                    Interpolation::Barycentric,
+                ),
+                None => f32::NAN,
+            }
+        })
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Smallest and largest elevation in the map.
+    pub fn range(&self) -> (f32, f32) {
+        self.data
+            .iter()
+            .filter(|v| !v.is_nan())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            })
+    }
+
+    /// Elevations quantized so that `min` maps to 0 and `max` to `u16::MAX`.
+    pub fn to_u16(&self, min: f32, max: f32) -> Vec<u16> {
+        let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+        self.data
+            .iter()
+            .map(|v| {
+                // NaN saturates to zero when cast
+                let t = ((v - min) * scale).clamp(0.0, 1.0);
+                (t * u16::MAX as f32).round() as u16
+            })
+            .collect()
+    }
+
+    /// 16-bit grayscale image mapping `min` to black and `max` to white.
+    pub fn to_gray16(&self, min: f32, max: f32) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        ImageBuffer::from_raw(self.width, self.height, self.to_u16(min, max))
+            .expect("heightmap data should match its dimensions")
+    }
+
+    /// Write a 16-bit grayscale PNG spanning the full range of the map.
+    pub fn write_png16(&self, path: impl AsRef<Path>) -> Result<()> {
+        let (min, max) = self.range();
+        self.to_gray16(min, max).save(path)?;
+        Ok(())
+    }
+
+    /// Write the raw elevations as little-endian `f32`s in row-major order.
+    pub fn write_raw_f32(&self, path: impl AsRef<Path>) -> Result<()> {
+        let bytes: Vec<u8> = self.data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Write elevations quantized over the full range of the map as
+    /// little-endian `u16`s in row-major order.
+    pub fn write_raw_u16(&self, path: impl AsRef<Path>) -> Result<()> {
+        let (min, max) = self.range();
+        let bytes: Vec<u8> = self
+            .to_u16(min, max)
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// JSON description of the map, needed to turn the quantized values of
+    /// the PNG and `u16` exports back into elevations. Values that aren't
+    /// finite, such as the range of a map with no samples, are `null`.
+    pub fn sidecar_json(&self, sea_level: f32) -> String {
+        let (min, max) = self.range();
+        let [min, max, sea_level] = [min, max, sea_level].map(json_number);
+        format!(
+            concat!(
+                "{{\n",
+                "  \"width\": {},\n",
+                "  \"height\": {},\n",
+                "  \"min_elevation\": {},\n",
+                "  \"max_elevation\": {},\n",
+                "  \"sea_level\": {},\n",
+                "  \"byte_order\": \"little_endian\"\n",
+                "}}\n"
+            ),
+            self.width, self.height, min, max, sea_level
+        )
+    }
+
+    pub fn write_sidecar(&self, path: impl AsRef<Path>, sea_level: f32) -> Result<()> {
+        fs::write(path, self.sidecar_json(sea_level))?;
+        Ok(())
+    }
+}
+
+// JSON has no NaN or infinities
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_stored_row_by_row() {
+        let map = Heightmap::from_fn(3, 2, |x, y| (y * 10 + x) as f32);
+        assert_eq!(map.data, [0.0, 1.0, 2.0, 10.0, 11.0, 12.0]);
+        assert_eq!(map.get(2, 1), 12.0);
+    }
+
+    #[test]
+    fn sidecar_json_has_no_non_finite_numbers() {
+        let map = Heightmap::from_fn(2, 1, |x, _| x as f32 * 100.0 - 50.0);
+        let json = map.sidecar_json(0.0);
+        assert!(json.contains("\"min_elevation\": -50,"), "{}", json);
+        assert!(json.contains("\"max_elevation\": 50,"), "{}", json);
+
+        let empty = Heightmap::from_fn(2, 1, |_, _| f32::NAN);
+        let json = empty.sidecar_json(f32::NAN);
+        assert!(json.contains("\"min_elevation\": null,"), "{}", json);
+        assert!(json.contains("\"max_elevation\": null,"), "{}", json);
+        assert!(json.contains("\"sea_level\": null,"), "{}", json);
+        assert!(!json.contains("inf") && !json.contains("NaN"), "{}", json);
+    }
+}
@@ -0,0 +1,133 @@
+use glam::{Vec2, Vec3};
+use image::{Rgba, RgbaImage};
+
+use super::{to_rgb8, ColorRamp, Interpolation};
+use crate::{
+    coords::{CellLocator, LatLon},
+    field::FieldValue,
+    projection::Projection,
+};
+
+/// Render a per-cell scalar field such as elevation or temperature to a map
+/// in the given projection, colouring values with `ramp`.
+///
+/// Pixels outside the projected map are transparent. The returned image can
+/// be written with `image.save("map.png")`.
+pub fn render_scalar(
+    locator: &CellLocator,
+    values: &[f32],
+    ramp: &ColorRamp,
+    projection: &impl Projection,
+    width: u32,
+    height: u32,
+    interpolation: Interpolation,
+) -> RgbaImage {
+    assert_eq!(values.len(), locator.get_sphere().num_cells());
+    render(projection, width, height, |lat_lon| {
+        ramp.sample(f32::sample(
+            values,
+            locator,
+            lat_lon.to_unit_vec3(),
+            interpolation,
+        ))
+    })
+}
+
+/// Render a per-cell colour field such as biome or plate colours to a map
+/// in the given projection.
+pub fn render_color(
+    locator: &CellLocator,
+    colors: &[[f32; 3]],
+    projection: &impl Projection,
+    width: u32,
+    height: u32,
+    interpolation: Interpolation,
+) -> RgbaImage {
+    assert_eq!(colors.len(), locator.get_sphere().num_cells());
+    let colors: Vec<Vec3> = colors.iter().map(|&c| c.into()).collect();
+    render(projection, width, height, |lat_lon| {
+        Vec3::sample(&colors, locator, lat_lon.to_unit_vec3(), interpolation).into()
+    })
+}
+
+/// Coordinate frame that normals are expressed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NormalSpace {
+    /// Relative to the planet, with +z along the polar axis.
+    Object,
+    /// Relative to the undisplaced sphere at each pixel, with x to the
+    /// east, y to the north and z straight up.
+    Tangent,
+}
+
+/// Normal map of the terrain raised by `elevation`, packed into RGB as
+/// `0.5 + 0.5 * n`.
+pub fn render_normals(
+    locator: &CellLocator,
+    elevation: &[f32],
+    space: NormalSpace,
+    projection: &impl Projection,
+    width: u32,
+    height: u32,
+) -> RgbaImage {
+    let normals = locator.get_sphere().get_normals(elevation);
+    render(projection, width, height, |lat_lon| {
+        let up = lat_lon.to_unit_vec3();
+        let n = Vec3::sample(&normals, locator, up, Interpolation::Barycentric).normalize();
+        let n = match space {
+            NormalSpace::Object => n,
+            NormalSpace::Tangent => {
+                let (sin_lon, cos_lon) = lat_lon.lon.sin_cos();
+                let east = Vec3::new(-sin_lon, cos_lon, 0.0);
+                let north = up.cross(east);
+                Vec3::new(n.dot(east), n.dot(north), n.dot(up))
+            }
+        };
+        (n * 0.5 + 0.5).into()
+    })
+}
+
+/// Map from pixel coordinates to the projection plane, stretching the
+/// projection bounds over the whole image.
+pub fn pixel_to_plane(
+    projection: &impl Projection,
+    width: u32,
+    height: u32,
+    x: f32,
+    y: f32,
+) -> Vec2 {
+    let (min, max) = projection.bounds();
+    Vec2::new(
+        min.x + x / width as f32 * (max.x - min.x),
+        max.y - y / height as f32 * (max.y - min.y),
+    )
+}
+
+/// Inverse of [`pixel_to_plane`].
+pub fn plane_to_pixel(projection: &impl Projection, width: u32, height: u32, point: Vec2) -> Vec2 {
+    let (min, max) = projection.bounds();
+    Vec2::new(
+        (point.x - min.x) / (max.x - min.x) * width as f32,
+        (max.y - point.y) / (max.y - min.y) * height as f32,
+    )
+}
+
+// sample the position shown at the centre of every pixel
+fn render(
+    projection: &impl Projection,
+    width: u32,
+    height: u32,
+    pixel: impl Fn(LatLon) -> [f32; 3],
+) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let point = pixel_to_plane(projection, width, height, x as f32 + 0.5, y as f32 + 0.5);
+        match projection.inverse(point) {
+            Some(lat_lon) => {
+                let [r, g, b] = to_rgb8(pixel(lat_lon)).0;
+                Rgba([r, g, b, 255])
+            }
+            None => Rgba([0; 4]),
+        }
+    })
+}
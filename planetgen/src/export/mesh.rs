@@ -0,0 +1,426 @@
+use std::{
+    f32::consts::{PI, TAU},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::ensure;
+use glam::{DVec3, Vec3};
+
+use crate::{coords::LatLon, icosphere::Icosphere, Result};
+
+/// Extra per-vertex values written as PLY properties.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Attribute {
+    F32(Vec<f32>),
+    U32(Vec<u32>),
+}
+impl Attribute {
+    fn len(&self) -> usize {
+        match self {
+            Attribute::F32(v) => v.len(),
+            Attribute::U32(v) => v.len(),
+        }
+    }
+}
+
+/// Triangle mesh with optional per-vertex attributes, ready to be written
+/// to mesh file formats.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub tex_coords: Option<Vec<[f32; 2]>>,
+    pub colors: Option<Vec<[f32; 3]>>,
+    /// Counter-clockwise triangles seen from outside.
+    pub indices: Vec<u32>,
+    pub attributes: Vec<(String, Attribute)>,
+    // cell each vertex was created from, when built from an icosphere
+    cells: Option<Vec<usize>>,
+}
+impl MeshData {
+    /// Bare mesh of positions and triangles.
+    pub fn new(positions: Vec<[f32; 3]>, indices: Vec<u32>) -> Self {
+        MeshData {
+            positions,
+            indices,
+            ..Default::default()
+        }
+    }
+
+    /// Mesh of an icosphere with each cell raised by its elevation, along
+    /// with normals and equirectangular texture coordinates.
+    ///
+    /// Vertices of faces crossing the antimeridian are duplicated so that
+    /// texture coordinates don't wrap across the whole texture, so use
+    /// [`MeshData::with_cell_colors`] and [`MeshData::with_cell_attribute`]
+    /// to attach per-cell data.
+    pub fn from_icosphere(sphere: &Icosphere, elevation: &[f32]) -> Self {
+        MeshData::from_icosphere_relative(sphere, elevation, DVec3::ZERO)
+    }
+    /// Like [`MeshData::from_icosphere`] with positions relative to `origin`,
+    /// for rendering a planet too large for `f32` positions around a camera.
+    pub fn from_icosphere_relative(sphere: &Icosphere, elevation: &[f32], origin: DVec3) -> Self {
+        assert_eq!(elevation.len(), sphere.num_cells());
+        let positions = sphere.get_relative_positions(elevation, origin);
+        let normals = sphere.get_normals(elevation);
+        let uv = |cell: usize| uv_of(sphere.get_position(cell));
+
+        // longitude is undefined at the poles, so they get a vertex per face
+        let is_pole = |cell: usize| {
+            let pos = sphere.get_position(cell);
+            pos.x.hypot(pos.y) <= pos.length() * 1e-6
+        };
+
+        let mut cells: Vec<usize> = (0..sphere.num_cells()).collect();
+        let mut tex_coords: Vec<[f32; 2]> = cells.iter().map(|&c| uv(c)).collect();
+        let mut indices = Vec::with_capacity(sphere.get_faces().len() * 3);
+        for face in sphere.get_faces() {
+            let corners = face.get_corners();
+            let us = corners.map(|c| tex_coords[c][0]);
+            let max_u = corners
+                .iter()
+                .zip(us)
+                .filter(|&(&c, _)| !is_pole(c))
+                .fold(0.0, |max, (_, u)| f32::max(max, u));
+            // shift corners on the far side of the seam past u = 1
+            let unwrap = |u: f32| if max_u - u > 0.5 { u + 1.0 } else { u };
+            for (corner, u) in corners.into_iter().zip(us) {
+                if is_pole(corner) {
+                    let centroid = corners
+                        .iter()
+                        .fold(Vec3::ZERO, |sum, &c| sum + sphere.get_position(c));
+                    let u = unwrap(uv_of(centroid)[0]);
+                    indices.push(cells.len() as u32);
+                    cells.push(corner);
+                    tex_coords.push([u, tex_coords[corner][1]]);
+                } else if unwrap(u) != u {
+                    indices.push(cells.len() as u32);
+                    cells.push(corner);
+                    tex_coords.push([unwrap(u), tex_coords[corner][1]]);
+                } else {
+                    indices.push(corner as u32);
+                }
+            }
+        }
+
+        MeshData {
+            positions: cells.iter().map(|&c| positions[c].into()).collect(),
+            normals: Some(cells.iter().map(|&c| normals[c].into()).collect()),
+            tex_coords: Some(tex_coords),
+            colors: None,
+            indices,
+            attributes: Vec::new(),
+            cells: Some(cells),
+        }
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Colour vertices by the cell they belong to.
+    pub fn with_cell_colors(mut self, colors: &[[f32; 3]]) -> Self {
+        self.colors = Some(self.per_vertex(colors));
+        self
+    }
+
+    /// Attach a per-cell attribute such as elevation or plate id.
+    pub fn with_cell_attribute(self, name: &str, values: Attribute) -> Self {
+        let values = match values {
+            Attribute::F32(v) => Attribute::F32(self.per_vertex(&v)),
+            Attribute::U32(v) => Attribute::U32(self.per_vertex(&v)),
+        };
+        self.with_attribute(name, values)
+    }
+
+    /// Attach a per-vertex attribute.
+    pub fn with_attribute(mut self, name: &str, values: Attribute) -> Self {
+        assert_eq!(values.len(), self.num_vertices());
+        self.attributes.push((name.to_owned(), values));
+        self
+    }
+
+    fn per_vertex<T: Copy>(&self, per_cell: &[T]) -> Vec<T> {
+        match &self.cells {
+            Some(cells) => cells.iter().map(|&c| per_cell[c]).collect(),
+            None => {
+                assert_eq!(per_cell.len(), self.num_vertices());
+                per_cell.to_vec()
+            }
+        }
+    }
+
+    /// Write a Wavefront OBJ file. Colours are written after each position,
+    /// an extension read by Blender and MeshLab. OBJ has no way to store
+    /// the extra attributes, so they are skipped.
+    pub fn write_obj(&self, mut w: impl Write) -> Result<()> {
+        writeln!(w, "# planetgen")?;
+        for (i, [x, y, z]) in self.positions.iter().enumerate() {
+            match &self.colors {
+                Some(colors) => {
+                    let [r, g, b] = colors[i];
+                    writeln!(w, "v {} {} {} {} {} {}", x, y, z, r, g, b)?;
+                }
+                None => writeln!(w, "v {} {} {}", x, y, z)?,
+            }
+        }
+        if let Some(tex_coords) = &self.tex_coords {
+            for [u, v] in tex_coords {
+                // obj puts v = 0 at the bottom of the texture
+                writeln!(w, "vt {} {}", u, 1.0 - v)?;
+            }
+        }
+        if let Some(normals) = &self.normals {
+            for [x, y, z] in normals {
+                writeln!(w, "vn {} {} {}", x, y, z)?;
+            }
+        }
+        for triangle in self.indices.chunks_exact(3) {
+            write!(w, "f")?;
+            for i in triangle {
+                // obj indices start at 1
+                let i = i + 1;
+                match (self.tex_coords.is_some(), self.normals.is_some()) {
+                    (true, true) => write!(w, " {}/{}/{}", i, i, i)?,
+                    (true, false) => write!(w, " {}/{}", i, i)?,
+                    (false, true) => write!(w, " {}//{}", i, i)?,
+                    (false, false) => write!(w, " {}", i)?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_obj(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_obj(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Write a binary little-endian PLY file including every attribute,
+    /// whose names must be letters, digits and underscores.
+    pub fn write_ply(&self, mut w: impl Write) -> Result<()> {
+        check_ply_names(self)?;
+        writeln!(w, "ply")?;
+        writeln!(w, "format binary_little_endian 1.0")?;
+        writeln!(w, "comment planetgen")?;
+        writeln!(w, "element vertex {}", self.num_vertices())?;
+        for name in ["x", "y", "z"] {
+            writeln!(w, "property float {}", name)?;
+        }
+        if self.normals.is_some() {
+            for name in ["nx", "ny", "nz"] {
+                writeln!(w, "property float {}", name)?;
+            }
+        }
+        if self.tex_coords.is_some() {
+            for name in ["s", "t"] {
+                writeln!(w, "property float {}", name)?;
+            }
+        }
+        if self.colors.is_some() {
+            for name in ["red", "green", "blue"] {
+                writeln!(w, "property uchar {}", name)?;
+            }
+        }
+        for (name, values) in &self.attributes {
+            let ty = match values {
+                Attribute::F32(_) => "float",
+                Attribute::U32(_) => "uint",
+            };
+            writeln!(w, "property {} {}", ty, name)?;
+        }
+        writeln!(w, "element face {}", self.indices.len() / 3)?;
+        writeln!(w, "property list uchar uint vertex_indices")?;
+        writeln!(w, "end_header")?;
+
+        let floats = |w: &mut dyn Write, values: &[f32]| -> std::io::Result<()> {
+            values
+                .iter()
+                .try_for_each(|v| w.write_all(&v.to_le_bytes()))
+        };
+        for i in 0..self.num_vertices() {
+            floats(&mut w, &self.positions[i])?;
+            if let Some(normals) = &self.normals {
+                floats(&mut w, &normals[i])?;
+            }
+            if let Some(tex_coords) = &self.tex_coords {
+                floats(&mut w, &tex_coords[i])?;
+            }
+            if let Some(colors) = &self.colors {
+                let rgb = super::to_rgb8(colors[i]);
+                w.write_all(&rgb.0)?;
+            }
+            for (_, values) in &self.attributes {
+                match values {
+                    Attribute::F32(v) => w.write_all(&v[i].to_le_bytes())?,
+                    Attribute::U32(v) => w.write_all(&v[i].to_le_bytes())?,
+                }
+            }
+        }
+        for triangle in self.indices.chunks_exact(3) {
+            w.write_all(&[3])?;
+            for i in triangle {
+                w.write_all(&i.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_ply(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_ply(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+// property names must be single words that don't clash with each other or
+// the standard vertex properties
+fn check_ply_names(mesh: &MeshData) -> Result<()> {
+    const STANDARD: [&str; 11] = [
+        "x", "y", "z", "nx", "ny", "nz", "s", "t", "red", "green", "blue",
+    ];
+    for (i, (name, _)) in mesh.attributes.iter().enumerate() {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        ensure!(
+            !name.is_empty() && name.chars().all(valid),
+            "Attribute name {:?} should be letters, digits and underscores",
+            name
+        );
+        ensure!(
+            !STANDARD.contains(&name.as_str()),
+            "Attribute name {:?} is taken by a standard PLY property",
+            name
+        );
+        ensure!(
+            mesh.attributes[..i].iter().all(|(other, _)| other != name),
+            "Two attributes are called {:?}",
+            name
+        );
+    }
+    Ok(())
+}
+
+// equirectangular texture coordinates of the direction of `pos`
+fn uv_of(pos: Vec3) -> [f32; 2] {
+    let lat_lon = LatLon::from_vec3(pos);
+    [lat_lon.lon / TAU + 0.5, 0.5 - lat_lon.lat / PI]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_keep_their_texture_coordinates_together() {
+        let sphere = Icosphere::subdivided(1.0, 3);
+        let mesh = MeshData::from_icosphere(&sphere, &vec![0.0; sphere.num_cells()]);
+        let tex_coords = mesh.tex_coords.as_ref().unwrap();
+        let mut poles = 0;
+        for triangle in mesh.indices.chunks_exact(3) {
+            let uvs = [0, 1, 2].map(|i| tex_coords[triangle[i] as usize]);
+            let (pole, others): (Vec<&[f32; 2]>, Vec<_>) = uvs
+                .iter()
+                .partition(|uv| uv[1] < 1e-6 || uv[1] > 1.0 - 1e-6);
+            let min = others.iter().map(|uv| uv[0]).fold(f32::INFINITY, f32::min);
+            let max = others
+                .iter()
+                .map(|uv| uv[0])
+                .fold(f32::NEG_INFINITY, f32::max);
+            // no face spans more than a fifth of the way around
+            assert!(max - min < 0.2 + 1e-4, "{:?}", uvs);
+            // a pole sits above the middle of its face
+            for uv in pole {
+                assert!(uv[0] > min && uv[0] < max, "{:?}", uvs);
+                poles += 1;
+            }
+        }
+        // one pole vertex for each face around either pole
+        assert_eq!(poles, 10);
+        let pole_vertices = tex_coords
+            .iter()
+            .filter(|uv| uv[1] < 1e-6 || uv[1] > 1.0 - 1e-6);
+        assert_eq!(pole_vertices.count(), 10 + 2);
+    }
+
+    fn planet_mesh() -> MeshData {
+        let sphere = Icosphere::subdivided(1.0, 1);
+        let cells = sphere.num_cells();
+        let ids: Vec<u32> = (0..cells as u32).collect();
+        MeshData::from_icosphere(&sphere, &vec![0.0; cells])
+            .with_cell_colors(&vec![[0.5; 3]; cells])
+            .with_cell_attribute("plate", Attribute::U32(ids))
+    }
+
+    #[test]
+    fn ply_header_describes_the_data() {
+        let mesh = planet_mesh();
+        let mut bytes = Vec::new();
+        mesh.write_ply(&mut bytes).unwrap();
+
+        let end = b"end_header\n";
+        let header_len = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = std::str::from_utf8(&bytes[..header_len]).unwrap();
+        let vertices = mesh.num_vertices();
+        let faces = mesh.indices.len() / 3;
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        assert!(header.contains(&format!("element vertex {}\n", vertices)));
+        assert!(header.contains(&format!("element face {}\n", faces)));
+        assert!(header.contains("property uint plate\n"));
+
+        // position, normal, texture coordinates, colour and plate id
+        let vertex_size = 12 + 12 + 8 + 3 + 4;
+        assert_eq!(
+            bytes.len(),
+            header_len + vertices * vertex_size + faces * (1 + 12)
+        );
+    }
+
+    #[test]
+    fn ply_attribute_names_must_be_plain_and_unique() {
+        for names in [&[""][..], &["sea level"], &["a\nb"], &["red"], &["a", "a"]] {
+            let mut mesh = MeshData::new(vec![[0.0; 3]; 3], vec![0, 1, 2]);
+            for name in names {
+                mesh = mesh.with_attribute(name, Attribute::F32(vec![0.0; 3]));
+            }
+            assert!(mesh.write_ply(&mut Vec::new()).is_err(), "{:?}", names);
+        }
+    }
+
+    #[test]
+    fn obj_faces_count_from_one() {
+        let mesh = MeshData::new(
+            vec![[0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            vec![0, 1, 2],
+        );
+        let mut bytes = Vec::new();
+        mesh.write_obj(&mut bytes).unwrap();
+        let obj = String::from_utf8(bytes).unwrap();
+        assert!(obj.ends_with("\nf 1 2 3\n"), "{}", obj);
+
+        let mesh = planet_mesh();
+        let mut bytes = Vec::new();
+        mesh.write_obj(&mut bytes).unwrap();
+        let obj = String::from_utf8(bytes).unwrap();
+        let vertices = obj.lines().filter(|l| l.starts_with("v ")).count();
+        assert_eq!(vertices, mesh.num_vertices());
+        let mut faces = 0;
+        for line in obj.lines().filter(|l| l.starts_with("f ")) {
+            for corner in line.split_whitespace().skip(1) {
+                let indices: Vec<usize> = corner.split('/').map(|i| i.parse().unwrap()).collect();
+                assert_eq!(indices.len(), 3);
+                assert!(indices.iter().all(|&i| i == indices[0]), "{}", line);
+                assert!((1..=vertices).contains(&indices[0]), "{}", line);
+            }
+            faces += 1;
+        }
+        assert_eq!(faces, mesh.indices.len() / 3);
+    }
+}
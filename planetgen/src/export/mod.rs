@@ -0,0 +1,139 @@
+pub mod cube;
+pub mod geojson;
+pub mod gltf;
+pub mod heightmap;
+pub mod map;
+pub mod mesh;
+pub mod stl;
+pub mod svg;
+
+use anyhow::ensure;
+
+use crate::Result;
+
+/// How values stored at cell centres are filled in between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Interpolation {
+    /// Take the value of the cell containing the point, which suits
+    /// categorical fields like plate ids.
+    Nearest,
+    /// Blend the three corners of the mesh triangle containing the point.
+    Barycentric,
+}
+
+/// Piecewise linear mapping from scalar values to colours.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Stops")
+)]
+pub struct ColorRamp {
+    stops: Vec<(f32, [f32; 3])>,
+}
+// a ramp as written in a file, checked by ColorRamp::try_new when read
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Stops {
+    stops: Vec<(f32, [f32; 3])>,
+}
+#[cfg(feature = "serde")]
+impl TryFrom<Stops> for ColorRamp {
+    type Error = anyhow::Error;
+    fn try_from(stops: Stops) -> Result<Self> {
+        ColorRamp::try_new(stops.stops)
+    }
+}
+impl ColorRamp {
+    /// Ramp through `stops` of a value and its colour, in any order.
+    ///
+    /// # Panics
+    /// Panics if there are no stops or a value is NaN.
+    pub fn new(stops: Vec<(f32, [f32; 3])>) -> Self {
+        ColorRamp::try_new(stops).unwrap()
+    }
+    /// Like [`ColorRamp::new`], returning an error rather than panicking.
+    pub fn try_new(mut stops: Vec<(f32, [f32; 3])>) -> Result<Self> {
+        ensure!(!stops.is_empty(), "A colour ramp needs at least one stop");
+        ensure!(
+            stops.iter().all(|s| !s.0.is_nan()),
+            "Colour ramp stops must have values"
+        );
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(ColorRamp { stops })
+    }
+    pub fn grayscale(min: f32, max: f32) -> Self {
+        ColorRamp::new(vec![(min, [0.0; 3]), (max, [1.0; 3])])
+    }
+    /// Blue below `sea_level`, then green through brown to white at `max`.
+    pub fn terrain(min: f32, sea_level: f32, max: f32) -> Self {
+        let land = |t: f32| sea_level + (max - sea_level) * t;
+        ColorRamp::new(vec![
+            (min, [0.0, 0.05, 0.3]),
+            (sea_level, [0.2, 0.45, 0.8]),
+            (land(0.0), [0.2, 0.55, 0.25]),
+            (land(0.4), [0.55, 0.6, 0.3]),
+            (land(0.75), [0.45, 0.35, 0.25]),
+            (max, [1.0, 1.0, 1.0]),
+        ])
+    }
+    pub fn sample(&self, value: f32) -> [f32; 3] {
+        let upper = self.stops.partition_point(|s| s.0 <= value);
+        if upper == 0 {
+            return self.stops[0].1;
+        }
+        if upper == self.stops.len() {
+            return self.stops[upper - 1].1;
+        }
+        let (v0, c0) = self.stops[upper - 1];
+        let (v1, c1) = self.stops[upper];
+        let t = (value - v0) / (v1 - v0);
+        [0, 1, 2].map(|i| c0[i] + (c1[i] - c0[i]) * t)
+    }
+}
+
+/// Distinct colour for a categorical id such as a plate id.
+pub fn palette(id: u32) -> [f32; 3] {
+    // step around the hue circle by the golden angle so that
+    // consecutive ids never look alike
+    let hue = (id as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // soften to pastel so boundaries drawn on top stay visible
+    [r, g, b].map(|c| 0.25 + 0.65 * c)
+}
+
+pub(crate) fn to_rgb8(color: [f32; 3]) -> image::Rgb<u8> {
+    image::Rgb(color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_need_stops_with_values() {
+        assert!(ColorRamp::try_new(Vec::new()).is_err());
+        assert!(ColorRamp::try_new(vec![(f32::NAN, [0.0; 3])]).is_err());
+        let ramp = ColorRamp::try_new(vec![(1.0, [1.0; 3]), (0.0, [0.0; 3])]).unwrap();
+        assert_eq!(ramp.sample(0.25), [0.25; 3]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ramps_are_checked_when_read() {
+        assert!(serde_json::from_str::<ColorRamp>(r#"{"stops": []}"#).is_err());
+        let ramp: ColorRamp =
+            serde_json::from_str(r#"{"stops": [[1.0, [1.0, 1.0, 1.0]], [0.0, [0.0, 0.0, 0.0]]]}"#)
+                .unwrap();
+        assert_eq!(ramp.sample(0.25), [0.25; 3]);
+    }
+}
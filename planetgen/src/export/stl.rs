@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::bail;
+use glam::Vec3;
+
+use super::mesh::MeshData;
+use crate::{icosphere::Icosphere, Result};
+
+/// Settings for turning a planet into a printable solid.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StlOptions {
+    /// Multiplier applied to elevations so relief is visible at print size.
+    pub exaggeration: f32,
+    /// Elevations below this are raised to it before exaggeration, keeping
+    /// deep trenches from producing fragile thin spots.
+    pub ocean_floor: Option<f32>,
+    /// Multiplier applied to every coordinate, e.g. to convert to millimetres.
+    pub scale: f32,
+}
+impl Default for StlOptions {
+    fn default() -> Self {
+        StlOptions {
+            exaggeration: 1.0,
+            ocean_floor: None,
+            scale: 1.0,
+        }
+    }
+}
+
+/// Closed mesh of the planet with each cell raised by its exaggerated
+/// elevation, sharing one vertex per cell.
+pub fn printable_mesh(sphere: &Icosphere, elevation: &[f32], options: &StlOptions) -> MeshData {
+    assert_eq!(elevation.len(), sphere.num_cells());
+    let positions = (0..sphere.num_cells())
+        .map(|cell| {
+            let e = match options.ocean_floor {
+                Some(floor) => elevation[cell].max(floor),
+                None => elevation[cell],
+            };
+            (sphere.get_displaced_position(cell, e * options.exaggeration) * options.scale).into()
+        })
+        .collect();
+    let indices = sphere
+        .get_faces()
+        .iter()
+        .flat_map(|f| f.get_corners().map(|c| c as u32))
+        .collect();
+    MeshData::new(positions, indices)
+}
+
+/// Cut a printable mesh at the equator into northern and southern halves,
+/// each closed with a flat cap and turned so the cap faces -z.
+///
+/// Relies on the equator being a loop of mesh edges, which holds for any
+/// icosphere subdivided at least once.
+pub fn split_hemispheres(mesh: &MeshData) -> Result<[MeshData; 2]> {
+    // points on the equator stay there when displaced radially, so only
+    // rounding error separates them from z = 0
+    let extent = mesh
+        .positions
+        .iter()
+        .fold(0.0f32, |m, p| m.max(Vec3::from(*p).length()));
+    let epsilon = extent * 1e-5;
+
+    let mut halves = [Vec::new(), Vec::new()];
+    for triangle in mesh.indices.chunks_exact(3) {
+        let z = triangle.iter().map(|&i| mesh.positions[i as usize][2]);
+        let (min, max) = z.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), z| {
+            (lo.min(z), hi.max(z))
+        });
+        if min >= -epsilon {
+            halves[0].extend_from_slice(triangle);
+        } else if max <= epsilon {
+            halves[1].extend_from_slice(triangle);
+        } else {
+            bail!("Mesh has triangles crossing the equator, so it can't be split");
+        }
+    }
+
+    let mut result = halves.map(|indices| cap(&mesh.positions, indices));
+    // flip the southern half over so its cap rests on the print bed too
+    for p in &mut result[1].positions {
+        *p = [p[0], -p[1], -p[2]];
+    }
+    Ok(result)
+}
+
+// close the open rim of a half mesh with a fan around the origin, keeping
+// only the vertices the half uses
+fn cap(positions: &[[f32; 3]], indices: Vec<u32>) -> MeshData {
+    let mut remap = HashMap::new();
+    let mut kept = Vec::new();
+    let mut indices: Vec<u32> = indices
+        .into_iter()
+        .map(|i| {
+            *remap.entry(i).or_insert_with(|| {
+                kept.push(positions[i as usize]);
+                kept.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    let mut edges = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+            // an edge shared with a neighbour appears once in each direction
+            if edges.remove(&(b, a)).is_none() {
+                edges.insert((a, b), ());
+            }
+        }
+    }
+
+    let centre = kept.len() as u32;
+    kept.push([0.0; 3]);
+    for (a, b) in edges.into_keys() {
+        indices.extend_from_slice(&[b, a, centre]);
+    }
+
+    MeshData::new(kept, indices)
+}
+
+/// Check that the mesh is a closed, consistently wound surface: every edge
+/// must be shared by exactly two triangles which traverse it in opposite
+/// directions.
+pub fn check_manifold(mesh: &MeshData) -> Result<()> {
+    let mut edges = HashMap::<(u32, u32), u32>::new();
+    for triangle in mesh.indices.chunks_exact(3) {
+        if triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[2] == triangle[0] {
+            bail!("Mesh has a degenerate triangle {:?}", triangle);
+        }
+        for i in 0..3 {
+            *edges
+                .entry((triangle[i], triangle[(i + 1) % 3]))
+                .or_default() += 1;
+        }
+    }
+    for (&(a, b), &count) in &edges {
+        if count > 1 {
+            bail!(
+                "Edge {}-{} is used by more than two triangles or is wound inconsistently",
+                a,
+                b
+            );
+        }
+        if !edges.contains_key(&(b, a)) {
+            bail!("Edge {}-{} is on an open boundary", a, b);
+        }
+    }
+    Ok(())
+}
+
+/// Write a binary STL file after checking the mesh is watertight.
+pub fn write_stl(mesh: &MeshData, mut w: impl Write) -> Result<()> {
+    check_manifold(mesh)?;
+
+    let mut header = [0u8; 80];
+    let title = b"planetgen";
+    header[..title.len()].copy_from_slice(title);
+    w.write_all(&header)?;
+    w.write_all(&((mesh.indices.len() / 3) as u32).to_le_bytes())?;
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(mesh.positions[triangle[i] as usize]));
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        for v in [normal, a, b, c] {
+            for x in v.to_array() {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+        // attribute byte count, unused
+        w.write_all(&0u16.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn save_stl(mesh: &MeshData, path: impl AsRef<Path>) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_stl(mesh, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh() -> MeshData {
+        let sphere = Icosphere::subdivided(1.0, 2);
+        let elevation: Vec<f32> = (0..sphere.num_cells())
+            .map(|cell| 0.05 * libm::sinf(cell as f32))
+            .collect();
+        let options = StlOptions {
+            exaggeration: 2.0,
+            ocean_floor: Some(-0.02),
+            scale: 10.0,
+        };
+        printable_mesh(&sphere, &elevation, &options)
+    }
+
+    #[test]
+    fn printable_meshes_are_closed() {
+        check_manifold(&mesh()).unwrap();
+    }
+
+    #[test]
+    fn open_and_doubled_edges_are_rejected() {
+        let mut open = mesh();
+        open.indices.truncate(open.indices.len() - 3);
+        let error = check_manifold(&open).unwrap_err();
+        assert!(error.to_string().contains("open boundary"), "{}", error);
+
+        let mut doubled = mesh();
+        let first: Vec<u32> = doubled.indices[..3].to_vec();
+        doubled.indices.extend(first);
+        let error = check_manifold(&doubled).unwrap_err();
+        assert!(error.to_string().contains("more than two"), "{}", error);
+    }
+
+    #[test]
+    fn hemispheres_are_closed_and_rest_on_their_caps() {
+        for half in split_hemispheres(&mesh()).unwrap() {
+            check_manifold(&half).unwrap();
+            assert!(half.positions.iter().all(|p| p[2] >= -1e-3));
+        }
+    }
+
+    #[test]
+    fn binary_stl_has_fifty_bytes_a_triangle() {
+        let mesh = mesh();
+        let triangles = mesh.indices.len() / 3;
+        let mut bytes = Vec::new();
+        write_stl(&mesh, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 84 + 50 * triangles);
+        assert_eq!(bytes[80..84], (triangles as u32).to_le_bytes());
+    }
+}
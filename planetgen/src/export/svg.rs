@@ -0,0 +1,752 @@
+use std::{collections::BTreeMap, fmt::Write as _, fs, path::Path};
+
+use glam::{Vec2, Vec3};
+
+use super::{map::plane_to_pixel, to_rgb8};
+use crate::{
+    coords::LatLon,
+    icosphere::Icosphere,
+    projection::Projection,
+    vector::{self, Boundary, BoundaryKind, Polyline, River},
+    Result,
+};
+
+// longest great circle step between projected points, so lines bend with
+// the projection
+const MAX_STEP: f32 = 2.0 * std::f32::consts::PI / 180.0;
+
+/// Vector map of the planet in a given projection, built up in layers and
+/// written as SVG.
+///
+/// Layers are drawn in the order they are added. Lines and shapes crossing
+/// a seam of the projection, such as the antimeridian, are cut there rather
+/// than drawn across the whole map, and parts the projection doesn't show
+/// are left out.
+pub struct SvgMap<'a, P: Projection> {
+    projection: &'a P,
+    width: u32,
+    height: u32,
+    defs: Vec<String>,
+    layers: Vec<String>,
+    legend: Vec<(String, Swatch)>,
+    land_clip: bool,
+}
+
+enum Swatch {
+    Fill(String),
+    Line(String),
+}
+
+impl<'a, P: Projection> SvgMap<'a, P> {
+    pub fn new(projection: &'a P, width: u32) -> Self {
+        SvgMap {
+            projection,
+            width,
+            height: projection.height_for_width(width),
+            defs: Vec::new(),
+            layers: Vec::new(),
+            legend: Vec::new(),
+            land_clip: false,
+        }
+    }
+
+    /// Fill the sea and land either side of `sea_level`.
+    ///
+    /// Regions added afterwards are clipped to the land.
+    pub fn add_land(
+        &mut self,
+        sphere: &Icosphere,
+        elevation: &[f32],
+        sea_level: f32,
+        land_color: [f32; 3],
+        ocean_color: [f32; 3],
+    ) {
+        let depth: Vec<f32> = elevation.iter().map(|e| -e).collect();
+        let ocean = self.merged_polygons(&vector::pieces_above(sphere, &depth, -sea_level));
+        let land = self.merged_polygons(&vector::pieces_above(sphere, elevation, sea_level));
+
+        self.layers.push(filled("ocean", &ocean, ocean_color));
+        self.layers.push(filled("land", &land, land_color));
+        self.defs.push(format!(
+            "<clipPath id=\"land-clip\"><path d=\"{}\"/></clipPath>",
+            land
+        ));
+        self.land_clip = true;
+        self.legend
+            .push(("Land".to_owned(), Swatch::Fill(hex(land_color))));
+        self.legend
+            .push(("Ocean".to_owned(), Swatch::Fill(hex(ocean_color))));
+    }
+
+    /// Fill each cell with the colour of its region, such as its biome.
+    ///
+    /// `labels` index into `regions`, which gives each a name for the
+    /// legend and a colour. Cells with labels past the end of `regions` are
+    /// left unfilled.
+    pub fn add_regions(
+        &mut self,
+        sphere: &Icosphere,
+        labels: &[u32],
+        regions: &[(&str, [f32; 3])],
+    ) {
+        assert_eq!(labels.len(), sphere.num_cells());
+        let mut outlines = vec![Vec::new(); regions.len()];
+        for (cell, &label) in labels.iter().enumerate() {
+            if let Some(outline) = outlines.get_mut(label as usize) {
+                outline.push(vector::cell_outline(sphere, cell));
+            }
+        }
+
+        let clip = if self.land_clip {
+            " clip-path=\"url(#land-clip)\""
+        } else {
+            ""
+        };
+        let mut group = format!("<g id=\"regions\"{}>\n", clip);
+        for (i, ((name, color), outlines)) in regions.iter().zip(outlines).enumerate() {
+            if outlines.is_empty() {
+                continue;
+            }
+            let id = format!("region-{}", i);
+            group.push_str(&filled(&id, &self.merged_polygons(&outlines), *color));
+            self.legend
+                .push((name.to_string(), Swatch::Fill(hex(*color))));
+        }
+        group.push_str("</g>\n");
+        self.layers.push(group);
+    }
+
+    pub fn add_coastlines(&mut self, coastlines: &[Polyline]) {
+        let style = "stroke=\"#333333\" stroke-width=\"0.8\"";
+        let d: String = coastlines.iter().map(|l| self.lines(l)).collect();
+        self.layers.push(stroked("coastlines", &d, style));
+        self.legend
+            .push(("Coastline".to_owned(), Swatch::Line(style.to_owned())));
+    }
+
+    /// Draw plate boundaries, coloured and dashed by their kind.
+    pub fn add_plate_boundaries(&mut self, boundaries: &[Boundary]) {
+        let mut by_kind = BTreeMap::<&str, String>::new();
+        for boundary in boundaries {
+            by_kind
+                .entry(boundary.kind.name())
+                .or_default()
+                .push_str(&self.lines(&boundary.line));
+        }
+        let mut group = String::from("<g id=\"plate-boundaries\">\n");
+        for kind in [
+            BoundaryKind::Convergent,
+            BoundaryKind::Divergent,
+            BoundaryKind::Transform,
+            BoundaryKind::Unknown,
+        ] {
+            if let Some(d) = by_kind.get(kind.name()) {
+                let style = boundary_style(kind);
+                group.push_str(&stroked(kind.name(), d, style));
+                let name = match kind {
+                    BoundaryKind::Unknown => "Plate boundary".to_owned(),
+                    kind => format!("{} boundary", capitalize(kind.name())),
+                };
+                self.legend.push((name, Swatch::Line(style.to_owned())));
+            }
+        }
+        group.push_str("</g>\n");
+        self.layers.push(group);
+    }
+
+    /// Draw rivers, wider for higher stream orders.
+    pub fn add_rivers(&mut self, rivers: &[River]) {
+        let mut by_order = BTreeMap::<u32, String>::new();
+        for river in rivers {
+            by_order
+                .entry(river.order)
+                .or_default()
+                .push_str(&self.lines(&river.line));
+        }
+        let mut group = String::from("<g id=\"rivers\">\n");
+        for (order, d) in &by_order {
+            group.push_str(&stroked(
+                &format!("order-{}", order),
+                d,
+                &river_style(*order),
+            ));
+        }
+        group.push_str("</g>\n");
+        self.layers.push(group);
+        if let Some(&order) = by_order.keys().last() {
+            self.legend
+                .push(("River".to_owned(), Swatch::Line(river_style(order))));
+        }
+    }
+
+    /// Draw lines of latitude and longitude every `step` degrees.
+    pub fn add_graticule(&mut self, step: f32) {
+        assert!(step > 0.0);
+        let point = |lat: f32, lon: f32| LatLon::from_degrees(lat, lon).to_unit_vec3();
+        let mut d = String::new();
+        let mut lat = -90.0 + step;
+        while lat < 90.0 {
+            let points = (0..360).map(|lon| point(lat, lon as f32)).collect();
+            d.push_str(&self.lines(&Polyline {
+                points,
+                closed: true,
+            }));
+            lat += step;
+        }
+        let mut lon = -180.0;
+        while lon < 180.0 {
+            // keep meridians just off seams that run exactly along them,
+            // where rounding would flip points between the sides
+            let points = (0..=180)
+                .map(|lat| point(lat as f32 - 90.0, lon + 1e-3))
+                .collect();
+            d.push_str(&self.lines(&Polyline {
+                points,
+                closed: false,
+            }));
+            lon += step;
+        }
+        self.layers.push(stroked(
+            "graticule",
+            &d,
+            "stroke=\"#000000\" stroke-opacity=\"0.25\" stroke-width=\"0.5\"",
+        ));
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            ),
+            w = self.width,
+            h = self.height
+        );
+        if !self.defs.is_empty() {
+            let _ = writeln!(svg, "<defs>\n{}\n</defs>", self.defs.join("\n"));
+        }
+        for layer in &self.layers {
+            svg.push_str(layer);
+        }
+        svg.push_str(&self.legend());
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_svg())?;
+        Ok(())
+    }
+
+    // key in the bottom left corner with a swatch for each kind of feature
+    fn legend(&self) -> String {
+        if self.legend.is_empty() {
+            return String::new();
+        }
+        let row = 16.0;
+        let box_height = row * self.legend.len() as f32 + 8.0;
+        let mut legend = format!(
+            "<g id=\"legend\" transform=\"translate(8 {:.1})\" font-family=\"sans-serif\" font-size=\"11\">\n",
+            self.height as f32 - box_height - 8.0
+        );
+        let _ = writeln!(
+            legend,
+            "<rect width=\"150\" height=\"{:.1}\" fill=\"#ffffff\" fill-opacity=\"0.8\" stroke=\"#999999\" stroke-width=\"0.5\"/>",
+            box_height
+        );
+        for (i, (name, swatch)) in self.legend.iter().enumerate() {
+            let y = 4.0 + row * i as f32;
+            let _ = match swatch {
+                Swatch::Fill(color) => writeln!(
+                    legend,
+                    "<rect x=\"6\" y=\"{:.1}\" width=\"20\" height=\"10\" fill=\"{}\"/>",
+                    y + 2.0,
+                    color
+                ),
+                Swatch::Line(style) => writeln!(
+                    legend,
+                    "<path d=\"M6 {y:.1}H26\" fill=\"none\" {}/>",
+                    style,
+                    y = y + 7.0
+                ),
+            };
+            let _ = writeln!(
+                legend,
+                "<text x=\"32\" y=\"{:.1}\">{}</text>",
+                y + 11.0,
+                escape(name)
+            );
+        }
+        legend.push_str("</g>\n");
+        legend
+    }
+
+    fn project(&self, pos: Vec3) -> Option<Vec2> {
+        self.projection
+            .forward(LatLon::from_vec3(pos))
+            .map(|p| plane_to_pixel(self.projection, self.width, self.height, p))
+    }
+
+    // pixel positions along a line, split where it jumps across a seam or
+    // leaves the map
+    fn project_path(&self, points: &[Vec3]) -> Vec<Vec<Vec2>> {
+        let points = separate(points);
+        let mut parts = Vec::new();
+        let mut part = Vec::new();
+        let first = match points.first() {
+            Some(&first) => first,
+            None => return parts,
+        };
+        let mut prev = (first, self.project(first));
+        if let Some(p) = prev.1 {
+            part.push(p);
+        }
+        for &point in &points[1..] {
+            if point == prev.0 {
+                continue;
+            }
+            // add points along the great circle so long lines follow the
+            // projection's curvature
+            let steps = (crate::coords::angle_between(prev.0, point) / MAX_STEP)
+                .ceil()
+                .max(1.0) as usize;
+            for i in 1..=steps {
+                let pos = prev.0.lerp(point, i as f32 / steps as f32).normalize();
+                let next = (pos, self.project(pos));
+                self.step(prev, next, &mut part, &mut parts);
+                prev = next;
+            }
+        }
+        if part.len() > 1 {
+            parts.push(part);
+        }
+        parts
+    }
+
+    fn step(
+        &self,
+        (a, pa): (Vec3, Option<Vec2>),
+        (b, pb): (Vec3, Option<Vec2>),
+        part: &mut Vec<Vec2>,
+        parts: &mut Vec<Vec<Vec2>>,
+    ) {
+        let mut finish = |part: &mut Vec<Vec2>| {
+            let done = std::mem::take(part);
+            if done.len() > 1 {
+                parts.push(done);
+            }
+        };
+        match (pa, pb) {
+            (Some(pa), Some(pb)) => {
+                // the projection of the middle of a step across a seam lands
+                // near one end, though strong distortion can do the same,
+                // so narrow it down to see whether the gap closes
+                if pa.distance(pb) > 1.0 && !self.halves(a, b, pa, pb) {
+                    let (lo, hi) = self.find_seam(a, b);
+                    if lo.distance(hi) > 1.0 {
+                        // rounding can flip points lying right on a seam
+                        // between its sides, so only run up to the seam from
+                        // the side each end is on
+                        if lo.distance(pa) < lo.distance(pb) {
+                            part.push(lo);
+                        }
+                        finish(part);
+                        if hi.distance(pb) < hi.distance(pa) {
+                            part.push(hi);
+                        }
+                    }
+                }
+                part.push(pb);
+            }
+            (Some(_), None) => {
+                part.push(self.find_edge(a, b));
+                finish(part);
+            }
+            (None, Some(pb)) => {
+                part.push(self.find_edge(b, a));
+                part.push(pb);
+            }
+            (None, None) => {}
+        }
+    }
+
+    // whether the middle of a step projects roughly halfway along it
+    fn halves(&self, a: Vec3, b: Vec3, pa: Vec2, pb: Vec2) -> bool {
+        match self.project((a + b).normalize()) {
+            Some(pm) => pa.distance(pm).max(pm.distance(pb)) < 0.75 * pa.distance(pb),
+            None => false,
+        }
+    }
+
+    // points either side of the largest jump between a and b
+    fn find_seam(&self, mut a: Vec3, mut b: Vec3) -> (Vec2, Vec2) {
+        let mut pa = self.project(a).unwrap();
+        let mut pb = self.project(b).unwrap();
+        for _ in 0..24 {
+            let mid = (a + b).normalize();
+            let pm = match self.project(mid) {
+                Some(pm) => pm,
+                None => break,
+            };
+            if pa.distance(pm) > pm.distance(pb) {
+                b = mid;
+                pb = pm;
+            } else {
+                a = mid;
+                pa = pm;
+            }
+        }
+        (pa, pb)
+    }
+
+    // last point shown on the map going from `shown` towards `hidden`
+    fn find_edge(&self, mut shown: Vec3, mut hidden: Vec3) -> Vec2 {
+        let mut point = self.project(shown).unwrap();
+        for _ in 0..24 {
+            let mid = (shown + hidden).normalize();
+            match self.project(mid) {
+                Some(p) => {
+                    shown = mid;
+                    point = p;
+                }
+                None => hidden = mid,
+            }
+        }
+        point
+    }
+
+    // svg path data for an open or closed line
+    fn lines(&self, line: &Polyline) -> String {
+        let mut points = line.points.clone();
+        if line.closed && points.len() > 1 {
+            points.push(points[0]);
+        }
+        // shift the line a hair to its left, so that a line running right
+        // along a seam is drawn on one side of it rather than flicking
+        // between both. the ends stay put, as they may sit on a pole
+        let shifted: Vec<Vec3> = (0..points.len())
+            .map(|i| {
+                if i == 0 || i + 1 == points.len() {
+                    return points[i];
+                }
+                let left = points[i].cross(points[i + 1] - points[i - 1]);
+                (points[i] + left.normalize_or_zero() * 1e-4).normalize()
+            })
+            .collect();
+        self.project_path(&shifted)
+            .iter()
+            .map(|part| path_data(part, false))
+            .collect()
+    }
+
+    // svg path data for small polygons tiling an area, merging those well
+    // clear of any seam or edge of the map into rings around the whole area
+    // rather than drawing each one
+    fn merged_polygons(&self, polygons: &[Vec<Vec3>]) -> String {
+        let (clear, near_seam): (Vec<_>, Vec<_>) =
+            polygons.iter().cloned().partition(|p| self.is_clear(p));
+        let mut d = self.polygons(&near_seam);
+        for mut ring in vector::merge_polygons(&clear) {
+            ring.push(ring[0]);
+            for part in self.project_path(&ring) {
+                d.push_str(&path_data(&part, true));
+            }
+        }
+        d
+    }
+
+    // whether a polygon, grown a little so that it can't merely touch a
+    // seam, is shown whole without being cut
+    fn is_clear(&self, polygon: &[Vec3]) -> bool {
+        let centre = polygon.iter().fold(Vec3::ZERO, |sum, &p| sum + p);
+        let mut grown: Vec<Vec3> = polygon
+            .iter()
+            .map(|p| p.lerp(centre.normalize(), -0.25).normalize())
+            .collect();
+        grown.push(grown[0]);
+        grown.iter().all(|&p| self.project(p).is_some()) && self.project_path(&grown).len() == 1
+    }
+
+    // svg path data for small polygons, closing any piece cut by a seam
+    // with a straight line along it
+    fn polygons(&self, polygons: &[Vec<Vec3>]) -> String {
+        let mut d = String::new();
+        for polygon in polygons {
+            // pull the corners very slightly inside, so that edges lying
+            // exactly along a seam are projected on the side of the polygon
+            let centre = polygon
+                .iter()
+                .fold(Vec3::ZERO, |sum, &p| sum + p)
+                .normalize();
+            let corners: Vec<Vec3> = polygon
+                .iter()
+                .map(|p| p.lerp(centre, 1e-4).normalize())
+                .collect();
+            // start halfway along an edge, somewhere on the map and clear
+            // of any seam, so the ends of a cut ring belong to the same piece
+            let n = corners.len();
+            let middle = |i: usize| (corners[i] + corners[(i + 1) % n]).normalize();
+            let start = (0..n).find(|&i| {
+                let m = middle(i);
+                let towards = |q: Vec3| self.project(m.lerp(q, 1e-3).normalize());
+                match (towards(corners[i]), towards(corners[(i + 1) % n])) {
+                    (Some(a), Some(b)) => a.distance(b) < 1.0,
+                    _ => false,
+                }
+            });
+            let k = start.unwrap_or(0);
+            let mut ring = vec![middle(k)];
+            ring.extend((1..=n).map(|i| corners[(k + i) % n]));
+            ring.push(middle(k));
+            let mut parts = self.project_path(&ring);
+            if start.is_some() && parts.len() > 1 {
+                let last = parts.pop().unwrap();
+                parts[0].splice(0..0, last);
+            }
+            // leave out slivers cut off right at a corner or seam
+            for part in parts.iter().filter(|p| area(p).abs() > 0.5) {
+                d.push_str(&path_data(part, true));
+            }
+        }
+        d
+    }
+}
+
+// project each end of every step from just inside the step, so that points
+// exactly on a seam are shown on the side of the step they lead to, and
+// points at a pole, where longitude is undefined and many projections
+// stretch into a line, take the longitude of their neighbours
+fn separate(points: &[Vec3]) -> Vec<Vec3> {
+    let mut result = Vec::with_capacity(points.len() * 2);
+    for (i, &p) in points.iter().enumerate() {
+        let towards = |q: Vec3| p.lerp(q, 1e-3).normalize();
+        if i > 0 {
+            result.push(towards(points[i - 1]));
+        }
+        if i + 1 < points.len() {
+            result.push(towards(points[i + 1]));
+        }
+    }
+    result
+}
+
+fn path_data(points: &[Vec2], closed: bool) -> String {
+    let mut d = String::with_capacity(points.len() * 14);
+    let mut last = String::new();
+    for p in points {
+        let point = format!("{:.1} {:.1}", p.x, p.y);
+        // skip points that round to the one before
+        if point != last {
+            d.push(if last.is_empty() { 'M' } else { 'L' });
+            d.push_str(&point);
+            last = point;
+        }
+    }
+    if closed {
+        d.push('Z');
+    }
+    d
+}
+
+// signed area enclosed by a ring of points
+fn area(points: &[Vec2]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum::<f32>()
+        / 2.0
+}
+
+// a matching stroke hides the hairline gaps antialiasing leaves between
+// neighbouring pieces
+fn filled(id: &str, d: &str, color: [f32; 3]) -> String {
+    let color = hex(color);
+    format!(
+        "<path id=\"{}\" d=\"{}\" fill=\"{c}\" stroke=\"{c}\" stroke-width=\"0.5\" stroke-linejoin=\"round\"/>\n",
+        id,
+        d,
+        c = color
+    )
+}
+
+fn stroked(id: &str, d: &str, style: &str) -> String {
+    format!(
+        "<path id=\"{}\" d=\"{}\" fill=\"none\" stroke-linecap=\"round\" stroke-linejoin=\"round\" {}/>\n",
+        id,
+        d,
+        style
+    )
+}
+
+fn boundary_style(kind: BoundaryKind) -> &'static str {
+    match kind {
+        BoundaryKind::Convergent => "stroke=\"#d62728\" stroke-width=\"2\"",
+        BoundaryKind::Divergent => "stroke=\"#1f77b4\" stroke-width=\"2\" stroke-dasharray=\"6 3\"",
+        BoundaryKind::Transform => {
+            "stroke=\"#2ca02c\" stroke-width=\"1.5\" stroke-dasharray=\"1 3\""
+        }
+        BoundaryKind::Unknown => "stroke=\"#555555\" stroke-width=\"1.5\"",
+    }
+}
+
+fn river_style(order: u32) -> String {
+    format!(
+        "stroke=\"#2b6cb0\" stroke-width=\"{:.1}\"",
+        0.4 + 0.4 * order as f32
+    )
+}
+
+fn hex(color: [f32; 3]) -> String {
+    let [r, g, b] = to_rgb8(color).0;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projection::{
+        Equirectangular, GoodeHomolosine, IcosahedralNet, Mollweide, Orthographic,
+    };
+
+    // rings of the path data written by `path_data`
+    fn rings(d: &str) -> Vec<Vec<Vec2>> {
+        d.split('M')
+            .filter(|ring| !ring.is_empty())
+            .map(|ring| {
+                ring.trim_end_matches('Z')
+                    .split('L')
+                    .map(|point| {
+                        let (x, y) = point.split_once(' ').unwrap();
+                        Vec2::new(x.parse().unwrap(), y.parse().unwrap())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn total_area(d: &str) -> f32 {
+        rings(d).iter().map(|ring| area(ring)).sum()
+    }
+
+    fn point(lat: f32, lon: f32) -> Vec3 {
+        LatLon::from_degrees(lat, lon).to_unit_vec3()
+    }
+
+    // merging changes nothing but the number of rings drawn
+    fn assert_merges(projection: &impl Projection, pieces: &[Vec<Vec3>]) {
+        let map = SvgMap::new(projection, 512);
+        let merged = map.merged_polygons(pieces);
+        assert!(rings(&merged).len() * 4 < pieces.len());
+        let separate = map.polygons(pieces);
+        let (merged, separate) = (total_area(&merged), total_area(&separate));
+        assert!((merged - separate).abs() < separate.abs() * 1e-3);
+    }
+
+    #[test]
+    fn areas_are_merged_into_rings() {
+        let sphere = Icosphere::subdivided(1.0, 4);
+        // land around the antimeridian, so part of it is cut by the seam
+        let elevation: Vec<f32> = (0..sphere.num_cells())
+            .map(|c| -sphere.get_position(c).x - 0.2)
+            .collect();
+        let pieces = vector::pieces_above(&sphere, &elevation, 0.0);
+        assert_merges(&Equirectangular::default(), &pieces);
+        assert_merges(&Mollweide::default(), &pieces);
+        assert_merges(&GoodeHomolosine, &pieces);
+        assert_merges(&IcosahedralNet::new(), &pieces);
+        assert_merges(
+            &Orthographic {
+                center: LatLon::from_degrees(20.0, 150.0),
+            },
+            &pieces,
+        );
+    }
+
+    #[test]
+    fn regions_cover_what_their_cells_do() {
+        let sphere = Icosphere::subdivided(1.0, 3);
+        let labels: Vec<u32> = (0..sphere.num_cells())
+            .map(|c| (sphere.get_position(c).z > 0.3) as u32)
+            .collect();
+        let projection = Equirectangular::default();
+        let mut map = SvgMap::new(&projection, 360);
+        map.add_regions(
+            &sphere,
+            &labels,
+            &[("south", [0.0; 3]), ("north", [1.0; 3])],
+        );
+        let d: String = map.layers[0]
+            .split(" d=\"")
+            .skip(1)
+            .map(|path| path.split('"').next().unwrap())
+            .collect();
+        assert!(rings(&d).len() * 4 < sphere.num_cells());
+
+        let cells: Vec<Vec<Vec3>> = (0..sphere.num_cells())
+            .map(|c| vector::cell_outline(&sphere, c))
+            .collect();
+        let separate = total_area(&map.polygons(&cells));
+        assert!((total_area(&d) - separate).abs() < separate.abs() * 1e-3);
+    }
+
+    #[test]
+    fn lines_are_cut_at_the_antimeridian() {
+        let projection = Equirectangular::default();
+        let map = SvgMap::new(&projection, 360);
+        let line = Polyline {
+            points: vec![point(10.0, 170.0), point(10.0, -170.0)],
+            closed: false,
+        };
+        let parts = rings(&map.lines(&line));
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].last().unwrap().x > 359.0);
+        assert!(parts[1][0].x < 1.0);
+        assert!(parts.iter().flatten().all(|p| (p.y - 80.0).abs() < 1.0));
+    }
+
+    #[test]
+    fn seams_are_found_between_the_ends_of_a_step() {
+        let projection = Equirectangular::default();
+        let map = SvgMap::new(&projection, 360);
+        let (a, b) = (point(0.0, 175.0), point(0.0, -175.0));
+        let (pa, pb) = (map.project(a).unwrap(), map.project(b).unwrap());
+        assert!(!map.halves(a, b, pa, pb));
+        let (lo, hi) = map.find_seam(a, b);
+        assert!(lo.x > 359.9 && hi.x < 0.1, "{} {}", lo, hi);
+
+        // away from the seam the middle of a step lands in the middle
+        let (a, b) = (point(0.0, -5.0), point(0.0, 5.0));
+        let (pa, pb) = (map.project(a).unwrap(), map.project(b).unwrap());
+        assert!(map.halves(a, b, pa, pb));
+    }
+
+    #[test]
+    fn separate_nudges_points_along_their_steps() {
+        let points = [point(0.0, 0.0), point(0.0, 10.0), point(0.0, 20.0)];
+        let separated = separate(&points);
+        assert_eq!(separated.len(), 4);
+        let lons: Vec<f32> = separated
+            .iter()
+            .map(|&p| LatLon::from_vec3(p).to_degrees().1)
+            .collect();
+        assert!(lons[0] > 0.0 && lons[0] < 0.1);
+        assert!(lons[1] < 10.0 && lons[1] > 9.9);
+        assert!(lons[2] > 10.0 && lons[2] < 10.1);
+        assert!(lons[3] < 20.0 && lons[3] > 19.9);
+    }
+}
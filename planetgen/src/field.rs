@@ -0,0 +1,432 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Add, Deref, DerefMut, Div, Mul, Sub},
+};
+
+use glam::Vec3;
+
+use crate::{coords::CellLocator, export::Interpolation, icosphere::Icosphere, planet::CellField};
+
+/// One value of type `T` for every cell of a mesh, such as elevation or plate
+/// ids. Derefs to a slice indexed by cell.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Field<T> {
+    values: Vec<T>,
+}
+
+/// Types a [`Field`] can hold and a [`PlanetData`](crate::planet::PlanetData)
+/// can store: `f32`, categorical `u32` and `Vec3`.
+pub trait FieldValue: Copy + 'static {
+    /// Blend three values by weights summing to one. Categorical values take
+    /// the value with the largest weight instead.
+    fn blend(values: [Self; 3], weights: [f32; 3]) -> Self;
+
+    /// Value of per-cell `values` in the direction of `pos`, from the mesh
+    /// `locator` was built for.
+    fn sample(
+        values: &[Self],
+        locator: &CellLocator,
+        pos: Vec3,
+        interpolation: Interpolation,
+    ) -> Self {
+        match interpolation {
+            Interpolation::Nearest => values[locator.locate(pos)],
+            Interpolation::Barycentric => {
+                let (corners, weights) = locator.locate_triangle(pos);
+                Self::blend(corners.map(|c| values[c]), weights)
+            }
+        }
+    }
+
+    fn into_cell_field(field: Field<Self>) -> CellField;
+    fn from_cell_field(field: &CellField) -> Option<&Field<Self>>;
+    fn from_cell_field_mut(field: &mut CellField) -> Option<&mut Field<Self>>;
+}
+
+impl FieldValue for f32 {
+    fn blend(values: [f32; 3], weights: [f32; 3]) -> f32 {
+        (0..3).map(|i| values[i] * weights[i]).sum()
+    }
+    fn into_cell_field(field: Field<f32>) -> CellField {
+        CellField::Scalar(field)
+    }
+    fn from_cell_field(field: &CellField) -> Option<&Field<f32>> {
+        match field {
+            CellField::Scalar(field) => Some(field),
+            _ => None,
+        }
+    }
+    fn from_cell_field_mut(field: &mut CellField) -> Option<&mut Field<f32>> {
+        match field {
+            CellField::Scalar(field) => Some(field),
+            _ => None,
+        }
+    }
+}
+impl FieldValue for u32 {
+    fn blend(values: [u32; 3], weights: [f32; 3]) -> u32 {
+        let heaviest = (0..3).fold(
+            0,
+            |best, i| {
+                if weights[i] > weights[best] {
+                    i
+                } else {
+                    best
+                }
+            },
+        );
+        values[heaviest]
+    }
+    fn into_cell_field(field: Field<u32>) -> CellField {
+        CellField::Id(field)
+    }
+    fn from_cell_field(field: &CellField) -> Option<&Field<u32>> {
+        match field {
+            CellField::Id(field) => Some(field),
+            _ => None,
+        }
+    }
+    fn from_cell_field_mut(field: &mut CellField) -> Option<&mut Field<u32>> {
+        match field {
+            CellField::Id(field) => Some(field),
+            _ => None,
+        }
+    }
+}
+impl FieldValue for Vec3 {
+    fn blend(values: [Vec3; 3], weights: [f32; 3]) -> Vec3 {
+        (0..3).fold(Vec3::ZERO, |sum, i| sum + values[i] * weights[i])
+    }
+    fn into_cell_field(field: Field<Vec3>) -> CellField {
+        CellField::Vector(field)
+    }
+    fn from_cell_field(field: &CellField) -> Option<&Field<Vec3>> {
+        match field {
+            CellField::Vector(field) => Some(field),
+            _ => None,
+        }
+    }
+    fn from_cell_field_mut(field: &mut CellField) -> Option<&mut Field<Vec3>> {
+        match field {
+            CellField::Vector(field) => Some(field),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Field<T> {
+    pub fn new(values: Vec<T>) -> Self {
+        Field { values }
+    }
+    /// Field of `cells` values made by calling `f` with each cell.
+    pub fn from_fn(cells: usize, f: impl FnMut(usize) -> T) -> Self {
+        Field::new((0..cells).map(f).collect())
+    }
+    pub fn get_values(&self) -> &[T] {
+        &self.values
+    }
+    pub fn into_vec(self) -> Vec<T> {
+        self.values
+    }
+
+    /// Field of `f` applied to every value.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Field<U> {
+        Field::new(self.values.iter().map(f).collect())
+    }
+    /// Field of `f` applied to the values of both fields for every cell.
+    pub fn zip_with<U, V>(&self, other: &Field<U>, mut f: impl FnMut(&T, &U) -> V) -> Field<V> {
+        assert_eq!(self.len(), other.len());
+        Field::new(
+            self.values
+                .iter()
+                .zip(&other.values)
+                .map(|(a, b)| f(a, b))
+                .collect(),
+        )
+    }
+}
+impl<T: Clone> Field<T> {
+    /// Field with the same value in all `cells`.
+    pub fn filled(cells: usize, value: T) -> Self {
+        Field::new(vec![value; cells])
+    }
+}
+
+impl<T: FieldValue> Field<T> {
+    /// Value in the direction of `pos`, from the mesh `locator` was built
+    /// for.
+    pub fn sample(&self, locator: &CellLocator, pos: Vec3, interpolation: Interpolation) -> T {
+        T::sample(&self.values, locator, pos, interpolation)
+    }
+
+    /// Field on the cells of `to`, sampled from this field on the mesh
+    /// `locator` was built for.
+    pub fn resample(
+        &self,
+        locator: &CellLocator,
+        to: &Icosphere,
+        interpolation: Interpolation,
+    ) -> Field<T> {
+        Field::from_fn(to.num_cells(), |cell| {
+            self.sample(locator, to.get_position(cell), interpolation)
+        })
+    }
+
+    /// Extend the field over the cells [`Icosphere::subdivide`] added, each
+    /// taking the blend of the two cells whose edge it split.
+    pub fn subdivide(&mut self, parents: &[[usize; 2]]) {
+        self.values.reserve(parents.len());
+        for &[a, b] in parents {
+            let values = [self.values[a], self.values[b], self.values[b]];
+            self.values.push(T::blend(values, [0.5, 0.5, 0.0]));
+        }
+    }
+}
+
+impl<T: Copy + PartialOrd> Field<T> {
+    /// Smallest value, or `None` for an empty field. NaNs are ignored.
+    pub fn min(&self) -> Option<T> {
+        self.extreme(|v, m| v < m)
+    }
+    /// Largest value, or `None` for an empty field. NaNs are ignored.
+    pub fn max(&self) -> Option<T> {
+        self.extreme(|v, m| v > m)
+    }
+
+    fn extreme(&self, better: impl Fn(T, T) -> bool) -> Option<T> {
+        let mut best = None;
+        for &v in &self.values {
+            // NaN is the only value not comparable with itself
+            if v.partial_cmp(&v).is_some() && best.map_or(true, |m| better(v, m)) {
+                best = Some(v);
+            }
+        }
+        best
+    }
+}
+
+impl<T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>> Field<T> {
+    /// Average of the values, or `None` for an empty field.
+    pub fn mean(&self) -> Option<T> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let sum = self.values.iter().fold(T::default(), |sum, &v| sum + v);
+        Some(sum * (1.0 / self.values.len() as f32))
+    }
+}
+
+impl Field<f32> {
+    /// Counts of values in `bins` equal steps between the smallest and
+    /// largest value.
+    pub fn histogram(&self, bins: usize) -> Histogram {
+        assert!(bins > 0);
+        let min = self.min().unwrap_or(0.0);
+        let max = self.max().unwrap_or(0.0);
+        let mut counts = vec![0; bins];
+        for &v in &self.values {
+            if v.is_nan() {
+                continue;
+            }
+            let t = if max > min {
+                (v - min) / (max - min)
+            } else {
+                0.0
+            };
+            counts[((t * bins as f32) as usize).min(bins - 1)] += 1;
+        }
+        Histogram { min, max, counts }
+    }
+}
+
+impl Field<u32> {
+    /// Number of cells with each id.
+    pub fn counts(&self) -> BTreeMap<u32, usize> {
+        let mut counts = BTreeMap::new();
+        for &id in &self.values {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+impl Field<Vec3> {
+    /// Length of every vector.
+    pub fn lengths(&self) -> Field<f32> {
+        self.map(|v| v.length())
+    }
+}
+
+/// Distribution of a scalar field, from [`Field::histogram`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    /// Number of values in each bin, lowest first.
+    pub counts: Vec<usize>,
+}
+impl Histogram {
+    pub fn bin_width(&self) -> f32 {
+        (self.max - self.min) / self.counts.len() as f32
+    }
+    /// Lower and upper bound of bin `i`.
+    pub fn get_bin_range(&self, i: usize) -> (f32, f32) {
+        let width = self.bin_width();
+        (
+            self.min + width * i as f32,
+            self.min + width * (i + 1) as f32,
+        )
+    }
+}
+
+impl<T> Deref for Field<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.values
+    }
+}
+impl<T> DerefMut for Field<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+}
+impl<T> From<Vec<T>> for Field<T> {
+    fn from(values: Vec<T>) -> Self {
+        Field::new(values)
+    }
+}
+impl<T> FromIterator<T> for Field<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Field::new(iter.into_iter().collect())
+    }
+}
+
+// elementwise arithmetic between fields, and with a single f32
+macro_rules! field_op {
+    ($op:ident, $method:ident) => {
+        impl<T: Copy + $op<Output = T>> $op for &Field<T> {
+            type Output = Field<T>;
+            fn $method(self, other: &Field<T>) -> Field<T> {
+                self.zip_with(other, |&a, &b| a.$method(b))
+            }
+        }
+        impl<T: Copy + $op<f32, Output = T>> $op<f32> for &Field<T> {
+            type Output = Field<T>;
+            fn $method(self, other: f32) -> Field<T> {
+                self.map(|&a| a.$method(other))
+            }
+        }
+    };
+}
+field_op!(Add, add);
+field_op!(Sub, sub);
+field_op!(Mul, mul);
+field_op!(Div, div);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_ignore_nan() {
+        let field = Field::new(vec![2.0, f32::NAN, 0.0, 3.0, 1.0]);
+        assert_eq!(field.min(), Some(0.0));
+        assert_eq!(field.max(), Some(3.0));
+
+        let histogram = field.histogram(2);
+        assert_eq!((histogram.min, histogram.max), (0.0, 3.0));
+        assert_eq!(histogram.counts, vec![2, 2]);
+        assert_eq!(histogram.get_bin_range(1), (1.5, 3.0));
+
+        let nan = Field::filled(3, f32::NAN);
+        assert_eq!(nan.min(), None);
+        assert_eq!(nan.max(), None);
+    }
+
+    #[test]
+    fn statistics_of_empty_and_constant_fields() {
+        let empty = Field::<f32>::default();
+        assert_eq!(empty.min(), None);
+        assert_eq!(empty.max(), None);
+        assert_eq!(empty.mean(), None);
+        assert_eq!(empty.histogram(4).counts, vec![0; 4]);
+
+        let constant = Field::filled(5, 1.5);
+        assert_eq!(constant.mean(), Some(1.5));
+        assert_eq!(constant.histogram(3).counts, vec![5, 0, 0]);
+    }
+
+    #[test]
+    fn means_of_scalars_and_vectors() {
+        let scalars = Field::new(vec![1.0, 2.0, 6.0]);
+        assert_eq!(scalars.mean(), Some(3.0));
+        let vectors = Field::new(vec![Vec3::X, Vec3::Y * 3.0]);
+        assert_eq!(vectors.mean(), Some(Vec3::new(0.5, 1.5, 0.0)));
+    }
+
+    #[test]
+    fn arithmetic_is_elementwise() {
+        let a = Field::new(vec![1.0, 2.0, 3.0]);
+        let b = Field::new(vec![4.0, 6.0, 8.0]);
+        assert_eq!(&a + &b, Field::new(vec![5.0, 8.0, 11.0]));
+        assert_eq!(&b - &a, Field::new(vec![3.0, 4.0, 5.0]));
+        assert_eq!(&a * &b, Field::new(vec![4.0, 12.0, 24.0]));
+        assert_eq!(&b / &a, Field::new(vec![4.0, 3.0, 8.0 / 3.0]));
+        assert_eq!(&a * 2.0, Field::new(vec![2.0, 4.0, 6.0]));
+        assert_eq!(&b / 2.0, Field::new(vec![2.0, 3.0, 4.0]));
+
+        let v = Field::new(vec![Vec3::X, Vec3::Y]);
+        assert_eq!(&(&v + &v) - &v, v);
+        assert_eq!(&v * 2.0, Field::new(vec![Vec3::X * 2.0, Vec3::Y * 2.0]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn arithmetic_needs_matching_lengths() {
+        let _ = &Field::new(vec![1.0, 2.0]) + &Field::new(vec![1.0]);
+    }
+
+    #[test]
+    fn subdividing_blends_the_parents() {
+        let mut sphere = Icosphere::new(1.0);
+        let cells = sphere.num_cells();
+        let mut positions = Field::from_fn(cells, |cell| sphere.get_position(cell));
+        let mut heights = Field::from_fn(cells, |cell| cell as f32);
+        let mut ids = Field::from_fn(cells, |cell| cell as u32);
+
+        let parents = sphere.subdivide();
+        positions.subdivide(&parents);
+        heights.subdivide(&parents);
+        ids.subdivide(&parents);
+        assert_eq!(positions.len(), sphere.num_cells());
+        assert_eq!(heights.len(), sphere.num_cells());
+        assert_eq!(ids.len(), sphere.num_cells());
+
+        for (i, &[a, b]) in parents.iter().enumerate() {
+            let cell = cells + i;
+            let direction = positions[cell].normalize();
+            assert!(direction.abs_diff_eq(sphere.get_position(cell).normalize(), 1e-5));
+            assert_eq!(heights[cell], (a + b) as f32 / 2.0);
+            assert!(ids[cell] == a as u32 || ids[cell] == b as u32);
+        }
+    }
+
+    #[test]
+    fn resampling_follows_the_field() {
+        let coarse = Icosphere::subdivided(1.0, 2);
+        let fine = Icosphere::subdivided(1.0, 3);
+        let locator = CellLocator::new(&coarse);
+        let z = Field::from_fn(coarse.num_cells(), |cell| coarse.get_position(cell).z);
+
+        let same = z.resample(&locator, &coarse, Interpolation::Nearest);
+        assert_eq!(same, z);
+
+        let resampled = z.resample(&locator, &fine, Interpolation::Barycentric);
+        assert_eq!(resampled.len(), fine.num_cells());
+        for (cell, &value) in resampled.iter().enumerate() {
+            assert!((value - fine.get_position(cell).z).abs() < 0.05);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+
+use super::field;
+use crate::{
+    coords::{self, LatLon},
+    field::Field,
+    parallel::map_cells,
+    pipeline::{Stage, StageContext},
+    planet::PlanetData,
+    Result,
+};
+
+// yearly precipitation in mm by absolute latitude in degrees: wet where air
+// rises at the equator and the polar fronts, dry where it sinks
+const PRECIPITATION_BANDS: [(f32, f32); 7] = [
+    (0.0, 2000.0),
+    (15.0, 1500.0),
+    (30.0, 300.0),
+    (45.0, 800.0),
+    (60.0, 1000.0),
+    (75.0, 400.0),
+    (90.0, 100.0),
+];
+
+/// Adds `temperature`, the mean in °C, and `precipitation`, in mm per year,
+/// from latitude, elevation and distance from the sea.
+#[derive(Debug, Clone, Copy)]
+pub struct Climate {
+    pub equator_temperature: f32,
+    pub pole_temperature: f32,
+    /// Cooling in °C per unit of elevation above sea level, per unit of
+    /// radius since elevations are exaggerated.
+    pub lapse_rate: f32,
+    /// Angle in radians inland over which precipitation falls off by a
+    /// factor of e.
+    pub moisture_reach: f32,
+}
+impl Default for Climate {
+    fn default() -> Self {
+        Climate {
+            equator_temperature: 28.0,
+            pole_temperature: -25.0,
+            lapse_rate: 1500.0,
+            moisture_reach: 0.3,
+        }
+    }
+}
+impl Stage for Climate {
+    fn name(&self) -> &str {
+        "climate"
+    }
+    fn inputs(&self) -> &[&str] {
+        &["elevation"]
+    }
+    fn outputs(&self) -> &[&str] {
+        &["temperature", "precipitation"]
+    }
+    fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+        let sphere = &planet.sphere;
+        let radius = sphere.get_radius();
+        let elevation = field::<f32>(planet, "elevation")?;
+
+        // breadth first distance in cells from the nearest sea
+        let mut hops = vec![usize::MAX; sphere.num_cells()];
+        let mut queue: VecDeque<usize> = (0..sphere.num_cells())
+            .filter(|&cell| elevation[cell] < 0.0)
+            .collect();
+        for &cell in &queue {
+            hops[cell] = 0;
+        }
+        while let Some(cell) = queue.pop_front() {
+            for &n in sphere.get_neighbors(cell) {
+                if hops[n] == usize::MAX {
+                    hops[n] = hops[cell] + 1;
+                    queue.push_back(n);
+                }
+            }
+        }
+        let step = match sphere.get_neighbors(0).first() {
+            Some(&n) => coords::angle_between(sphere.get_position(0), sphere.get_position(n)),
+            None => 0.0,
+        };
+
+        let (temperature, precipitation): (Vec<f32>, Vec<f32>) =
+            map_cells(sphere.num_cells(), |cell| {
+                let pos = sphere.get_position(cell);
+                let sin_lat = pos.z / pos.length();
+                let height = elevation[cell].max(0.0) / radius;
+                let temperature = self.equator_temperature
+                    + (self.pole_temperature - self.equator_temperature) * sin_lat * sin_lat
+                    - self.lapse_rate * height;
+
+                let (lat, _) = LatLon::from_vec3(pos).to_degrees();
+                let inland = if hops[cell] == usize::MAX {
+                    0.0
+                } else {
+                    libm::expf(-(hops[cell] as f32) * step / self.moisture_reach)
+                };
+                (temperature, band_precipitation(lat.abs()) * inland)
+            })
+            .into_iter()
+            .unzip();
+
+        planet.insert("temperature", Field::new(temperature));
+        planet.insert("precipitation", Field::new(precipitation));
+        Ok(())
+    }
+}
+
+fn band_precipitation(lat: f32) -> f32 {
+    for pair in PRECIPITATION_BANDS.windows(2) {
+        let ((lat0, p0), (lat1, p1)) = (pair[0], pair[1]);
+        if lat <= lat1 {
+            return p0 + (p1 - p0) * ((lat - lat0) / (lat1 - lat0)).clamp(0.0, 1.0);
+        }
+    }
+    PRECIPITATION_BANDS[PRECIPITATION_BANDS.len() - 1].1
+}
+
+/// Broad kind of landscape, classified by temperature and precipitation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Biome {
+    Ocean,
+    Ice,
+    Tundra,
+    Taiga,
+    TemperateForest,
+    Grassland,
+    Desert,
+    Savanna,
+    RainForest,
+}
+impl Biome {
+    /// Every biome, in the order of their ids.
+    pub const ALL: [Biome; 9] = [
+        Biome::Ocean,
+        Biome::Ice,
+        Biome::Tundra,
+        Biome::Taiga,
+        Biome::TemperateForest,
+        Biome::Grassland,
+        Biome::Desert,
+        Biome::Savanna,
+        Biome::RainForest,
+    ];
+
+    pub fn classify(elevation: f32, temperature: f32, precipitation: f32) -> Biome {
+        if elevation < 0.0 {
+            Biome::Ocean
+        } else if temperature < -10.0 {
+            Biome::Ice
+        } else if temperature < 0.0 {
+            Biome::Tundra
+        } else if temperature < 8.0 {
+            if precipitation < 400.0 {
+                Biome::Tundra
+            } else {
+                Biome::Taiga
+            }
+        } else if temperature < 20.0 {
+            if precipitation < 300.0 {
+                Biome::Desert
+            } else if precipitation < 800.0 {
+                Biome::Grassland
+            } else {
+                Biome::TemperateForest
+            }
+        } else if precipitation < 400.0 {
+            Biome::Desert
+        } else if precipitation < 1200.0 {
+            Biome::Savanna
+        } else {
+            Biome::RainForest
+        }
+    }
+
+    /// Id stored in the `biome` field.
+    pub fn get_id(self) -> u32 {
+        self as u32
+    }
+    pub fn from_id(id: u32) -> Option<Biome> {
+        Biome::ALL.get(id as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Ocean => "ocean",
+            Biome::Ice => "ice",
+            Biome::Tundra => "tundra",
+            Biome::Taiga => "taiga",
+            Biome::TemperateForest => "temperate forest",
+            Biome::Grassland => "grassland",
+            Biome::Desert => "desert",
+            Biome::Savanna => "savanna",
+            Biome::RainForest => "rain forest",
+        }
+    }
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            Biome::Ocean => [0.2, 0.35, 0.6],
+            Biome::Ice => [0.95, 0.95, 0.98],
+            Biome::Tundra => [0.6, 0.6, 0.5],
+            Biome::Taiga => [0.25, 0.4, 0.3],
+            Biome::TemperateForest => [0.2, 0.5, 0.2],
+            Biome::Grassland => [0.55, 0.7, 0.3],
+            Biome::Desert => [0.85, 0.75, 0.5],
+            Biome::Savanna => [0.7, 0.65, 0.3],
+            Biome::RainForest => [0.05, 0.4, 0.1],
+        }
+    }
+}
+
+/// Adds `biome`, the id of each cell's [`Biome`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Biomes;
+impl Stage for Biomes {
+    fn name(&self) -> &str {
+        "biomes"
+    }
+    fn inputs(&self) -> &[&str] {
+        &["elevation", "temperature", "precipitation"]
+    }
+    fn outputs(&self) -> &[&str] {
+        &["biome"]
+    }
+    fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+        let elevation = field::<f32>(planet, "elevation")?;
+        let temperature = field::<f32>(planet, "temperature")?;
+        let precipitation = field::<f32>(planet, "precipitation")?;
+        let biomes = map_cells(planet.sphere.num_cells(), |cell| {
+            Biome::classify(elevation[cell], temperature[cell], precipitation[cell]).get_id()
+        });
+        planet.insert("biome", Field::new(biomes));
+        Ok(())
+    }
+}
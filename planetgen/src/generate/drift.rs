@@ -0,0 +1,281 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use glam::{Mat3, Vec3};
+
+use super::{
+    field,
+    tectonics::{CONTINENT, OCEAN_FLOOR},
+};
+use crate::{
+    coords::CellLocator,
+    field::Field,
+    parallel::map_cells,
+    pipeline::{Stage, StageContext},
+    planet::PlanetData,
+    save::save_planet,
+    timeline::{Frame, Timeline},
+    Result,
+};
+
+// thickest crust collisions pile up, as a fraction of the radius
+const MOUNTAIN_CRUST: f32 = 4.0 * CONTINENT;
+
+// plate ids and crust at the start of the drift, which each plate carries
+// round unchanged apart from collisions, kept until the drift is done
+const START_PLATE: &str = "drift.plate";
+const START_CRUST: &str = "drift.crust";
+
+/// Moves the plates for a number of steps, carrying `plate` and `crust`
+/// along with them. Crust piles up where plates collide and new ocean floor
+/// opens where they part. Updates `velocity` and the planet's time, and can
+/// record the plates and crust of each step in the planet's [`Timeline`].
+///
+/// A planet loaded from one of the drift's [`Checkpoints`] carries on from
+/// where it was saved, finishing the same as an uninterrupted run, with
+/// [`Pipeline::run_from`](crate::pipeline::Pipeline::run_from) and `"drift"`.
+#[derive(Debug, Clone)]
+pub struct Drift {
+    pub steps: u32,
+    /// Simulation time per step. Plates turn by up to one radian per unit of
+    /// time, and a step moving them by more than a cell skips over cells.
+    pub time_step: f32,
+    /// Crust added where plates collide, per step, as a fraction of the
+    /// radius.
+    pub uplift: f32,
+    /// Steps between frames added to the planet's [`Timeline`], or 0 to
+    /// record none.
+    pub record_every: u32,
+    pub checkpoints: Option<Checkpoints>,
+}
+impl Default for Drift {
+    fn default() -> Self {
+        Drift {
+            steps: 20,
+            time_step: 0.01,
+            uplift: 0.0005,
+            record_every: 0,
+            checkpoints: None,
+        }
+    }
+}
+impl Stage for Drift {
+    fn name(&self) -> &str {
+        "drift"
+    }
+    fn inputs(&self) -> &[&str] {
+        &["plate", "velocity", "crust"]
+    }
+    fn outputs(&self) -> &[&str] {
+        &["plate", "velocity", "crust"]
+    }
+    fn cost(&self) -> f32 {
+        self.steps as f32 * 2.0
+    }
+    fn run(&mut self, planet: &mut PlanetData, context: &mut StageContext) -> Result<()> {
+        let time_step = self.time_step as f64;
+        // a planet part way through, as loaded from a checkpoint, still has
+        // where the plates started
+        let done = if planet.get_field(START_PLATE).is_some() && time_step > 0.0 {
+            ((planet.time / time_step).round() as u32).min(self.steps)
+        } else {
+            let plate = field::<u32>(planet, "plate")?.clone();
+            let crust = field::<f32>(planet, "crust")?.clone();
+            planet.insert(START_PLATE, plate);
+            planet.insert(START_CRUST, crust);
+            planet.time = 0.0;
+            planet.timeline = Timeline::new();
+            if self.record_every > 0 {
+                record(planet)?;
+            }
+            0
+        };
+        for step in done..self.steps {
+            context.check_cancelled()?;
+            context.report(step as f32 / self.steps as f32);
+            // set rather than added up, so resumed runs keep the same time
+            planet.time = (step + 1) as f64 * time_step;
+            self.advance(planet)?;
+            if self.record_every > 0
+                && ((step + 1) % self.record_every == 0 || step + 1 == self.steps)
+            {
+                record(planet)?;
+            }
+
+            if let Some(checkpoints) = &self.checkpoints {
+                let step = step + 1;
+                if checkpoints.every > 0 && step % checkpoints.every == 0 && step < self.steps {
+                    checkpoints.save(planet, step)?;
+                }
+            }
+        }
+        planet.remove_field(START_PLATE);
+        planet.remove_field(START_CRUST);
+        Ok(())
+    }
+}
+impl Drift {
+    // turn every plate from where it started to where it is at the planet's
+    // time, rather than by a step from where it was, so plates moving less
+    // than a cell per step don't get stuck
+    fn advance(&self, planet: &mut PlanetData) -> Result<()> {
+        let sphere = &planet.sphere;
+        let cells = sphere.num_cells();
+        let radius = sphere.get_radius();
+        let locator = CellLocator::new(sphere);
+        let ids = field::<u32>(planet, "plate")?;
+        let start_ids = field::<u32>(planet, START_PLATE)?;
+        let mut start_crust = field::<f32>(planet, START_CRUST)?.clone();
+        let time = planet.time as f32;
+        let back: Vec<Mat3> = planet
+            .plates
+            .iter()
+            .map(|plate| rotation(plate.axis, -plate.angular_speed * time))
+            .collect();
+
+        // the plate on top of each cell and where its crust started, and
+        // whether another plate is underneath
+        let claims = map_cells(cells, |cell| {
+            let pos = sphere.get_position(cell);
+            let mut candidates = vec![ids[cell]];
+            for &n in sphere.get_neighbors(cell) {
+                if !candidates.contains(&ids[n]) {
+                    candidates.push(ids[n]);
+                }
+            }
+
+            // the most buoyant crust ends up on top
+            let mut top: Option<(u32, usize)> = None;
+            let mut count = 0;
+            for plate in candidates {
+                let from = locator.locate(back[plate as usize] * pos);
+                if start_ids[from] != plate {
+                    continue;
+                }
+                count += 1;
+                if top.map_or(true, |(_, f)| start_crust[from] > start_crust[f]) {
+                    top = Some((plate, from));
+                }
+            }
+            (top, count > 1)
+        });
+
+        // thicken the crust plates carry into collisions, once however many
+        // cells it covers
+        let mut thickened = vec![false; cells];
+        for &(top, collided) in &claims {
+            if let (Some((_, from)), true) = (top, collided) {
+                if !thickened[from] {
+                    thickened[from] = true;
+                    start_crust[from] =
+                        (start_crust[from] + self.uplift * radius).min(MOUNTAIN_CRUST * radius);
+                }
+            }
+        }
+        // where plates have parted, new ocean floor stays with the plate
+        // that was there
+        let (ids, crust): (Vec<u32>, Vec<f32>) = claims
+            .iter()
+            .enumerate()
+            .map(|(cell, &(top, _))| match top {
+                Some((plate, from)) => (plate, start_crust[from]),
+                None => (ids[cell], OCEAN_FLOOR * radius),
+            })
+            .unzip();
+        let velocity = map_cells(cells, |cell| {
+            planet.plates[ids[cell] as usize].velocity(sphere.get_position(cell))
+        });
+
+        planet.insert("plate", Field::new(ids));
+        planet.insert("velocity", Field::new(velocity));
+        planet.insert("crust", Field::new(crust));
+        planet.insert(START_CRUST, start_crust);
+        Ok(())
+    }
+}
+
+fn record(planet: &mut PlanetData) -> Result<()> {
+    let frame = Frame {
+        time: planet.time,
+        plate: field::<u32>(planet, "plate")?.clone(),
+        crust: field::<f32>(planet, "crust")?.clone(),
+    };
+    planet.timeline.push(frame);
+    Ok(())
+}
+
+// rotation by `angle` about the unit `axis`, built with libm so it comes out
+// the same on every platform
+fn rotation(axis: Vec3, angle: f32) -> Mat3 {
+    let (sin, cos) = (libm::sinf(angle), libm::cosf(angle));
+    // cross product with the axis
+    let cross = Mat3::from_cols(
+        Vec3::new(0.0, axis.z, -axis.y),
+        Vec3::new(-axis.z, 0.0, axis.x),
+        Vec3::new(axis.y, -axis.x, 0.0),
+    );
+    Mat3::IDENTITY + cross * sin + cross * cross * (1.0 - cos)
+}
+
+/// Where and how often [`Drift`] saves the planet, so a long simulation can
+/// go on from its last save after a crash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoints {
+    pub dir: PathBuf,
+    /// Steps between saves.
+    pub every: u32,
+}
+impl Checkpoints {
+    pub fn new(dir: impl Into<PathBuf>, every: u32) -> Self {
+        Checkpoints {
+            dir: dir.into(),
+            every,
+        }
+    }
+
+    /// File the planet is saved to after `step` steps.
+    pub fn get_path(&self, step: u32) -> PathBuf {
+        self.dir.join(format!("drift-{:06}.planet", step))
+    }
+
+    /// Most recent checkpoint in the directory, if there is one.
+    pub fn latest(&self) -> Result<Option<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(None);
+        }
+        let mut latest = None;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if let Some(step) = checkpoint_step(&path) {
+                if latest.as_ref().map_or(true, |&(s, _)| step > s) {
+                    latest = Some((step, path));
+                }
+            }
+        }
+        Ok(latest.map(|(_, path)| path))
+    }
+
+    fn save(&self, planet: &PlanetData, step: u32) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Creating {}", self.dir.display()))?;
+        // write beside the checkpoint and rename, so a crash while saving
+        // never leaves a broken checkpoint behind
+        let path = self.get_path(step);
+        let partial = path.with_extension("planet.partial");
+        save_planet(planet, &partial).with_context(|| format!("Writing {}", partial.display()))?;
+        fs::rename(&partial, &path).with_context(|| format!("Writing {}", path.display()))?;
+        Ok(())
+    }
+}
+
+// step of a file named like a checkpoint
+fn checkpoint_step(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix("drift-")?
+        .strip_suffix(".planet")?
+        .parse()
+        .ok()
+}
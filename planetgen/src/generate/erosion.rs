@@ -0,0 +1,115 @@
+use super::field;
+use crate::{
+    field::Field,
+    parallel::map_cells,
+    pipeline::{Stage, StageContext},
+    planet::PlanetData,
+    vector::{drainage, flow_accumulation},
+    Result,
+};
+
+/// Wears down `elevation` above sea level: rivers cut into the land in
+/// proportion to the flow through them, and slopes steeper than the talus
+/// angle slump towards their lowest neighbour.
+#[derive(Debug, Clone, Copy)]
+pub struct Erosion {
+    pub iterations: u32,
+    /// Share of the drop to the next cell downstream a river removes per
+    /// iteration, for a river draining the whole planet.
+    pub strength: f32,
+    /// Steepest stable slope, in elevation per unit of distance.
+    pub talus: f32,
+}
+impl Default for Erosion {
+    fn default() -> Self {
+        Erosion {
+            iterations: 10,
+            strength: 0.5,
+            talus: 0.08,
+        }
+    }
+}
+impl Stage for Erosion {
+    fn name(&self) -> &str {
+        "erosion"
+    }
+    fn inputs(&self) -> &[&str] {
+        &["elevation"]
+    }
+    fn outputs(&self) -> &[&str] {
+        &["elevation"]
+    }
+    fn cost(&self) -> f32 {
+        self.iterations as f32
+    }
+    fn run(&mut self, planet: &mut PlanetData, context: &mut StageContext) -> Result<()> {
+        let sphere = &planet.sphere;
+        let cells = sphere.num_cells();
+        let mut elevation = field::<f32>(planet, "elevation")?.to_vec();
+        for iteration in 0..self.iterations {
+            context.check_cancelled()?;
+            context.report(iteration as f32 / self.iterations as f32);
+            let downstream = drainage(sphere, &elevation, 0.0);
+            let flow = flow_accumulation(&downstream);
+            // how deep each land cell's river cuts, and how much slumps to
+            // which neighbour
+            let moves = map_cells(cells, |cell| {
+                if elevation[cell] < 0.0 {
+                    return (None, None);
+                }
+                // never cut below the cell downstream, or below sea level
+                let cut = downstream[cell].map(|d| {
+                    let drop = elevation[cell] - elevation[d].max(0.0);
+                    let rate = (self.strength * (flow[cell] / cells as f32).sqrt()).min(0.5);
+                    rate * drop.max(0.0)
+                });
+
+                let pos = sphere.get_position(cell);
+                let lowest = sphere
+                    .get_neighbors(cell)
+                    .iter()
+                    .min_by(|&&a, &&b| elevation[a].total_cmp(&elevation[b]));
+                let slump = lowest.and_then(|&n| {
+                    let distance = (sphere.get_position(n) - pos).length();
+                    let excess = elevation[cell] - elevation[n] - self.talus * distance;
+                    // split the excess so neither cell overshoots
+                    (excess > 0.0).then(|| (n, excess / 4.0))
+                });
+                (cut, slump)
+            });
+
+            // apply the moves in order of the cell they come from, so the
+            // sums round the same however the cells were split up
+            elevation = map_cells(cells, |cell| {
+                let mut incoming: Vec<(usize, f32)> = sphere
+                    .get_neighbors(cell)
+                    .iter()
+                    .filter_map(|&m| match moves[m].1 {
+                        Some((n, share)) if n == cell => Some((m, share)),
+                        _ => None,
+                    })
+                    .collect();
+                incoming.sort_by_key(|&(m, _)| m);
+                let (before, after) =
+                    incoming.split_at(incoming.partition_point(|&(m, _)| m < cell));
+
+                let mut e = elevation[cell];
+                for &(_, share) in before {
+                    e += share;
+                }
+                if let (Some(cut), _) = moves[cell] {
+                    e -= cut;
+                }
+                if let (_, Some((_, share))) = moves[cell] {
+                    e -= share;
+                }
+                for &(_, share) in after {
+                    e += share;
+                }
+                e
+            });
+        }
+        planet.insert("elevation", Field::new(elevation));
+        Ok(())
+    }
+}
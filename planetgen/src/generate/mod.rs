@@ -0,0 +1,151 @@
+use anyhow::Context;
+use glam::Vec3;
+
+use crate::{
+    field::{Field, FieldValue},
+    parallel::map_cells,
+    pipeline::{Pipeline, Stage, StageContext},
+    planet::{GenerationParams, PlanetData},
+    rng::{mix, Rng},
+    Result,
+};
+
+mod climate;
+mod drift;
+mod erosion;
+mod tectonics;
+
+pub use climate::{Biome, Biomes, Climate};
+pub use drift::{Checkpoints, Drift};
+pub use erosion::Erosion;
+pub use tectonics::{grow_plates, Plates, Tectonics};
+
+/// Generate a planet with the [`standard_pipeline`].
+///
+/// Elevation is in the units of the radius, with sea level at 0.
+pub fn generate(params: &GenerationParams) -> PlanetData {
+    let mut planet = PlanetData::new(*params);
+    standard_pipeline()
+        .run(&mut planet)
+        .expect("the standard stages always run");
+    planet
+}
+
+/// The stages of a whole planet: mesh, plates, drift, tectonics, noise,
+/// erosion, climate and biomes, with default settings.
+///
+/// Together they add the fields `plate`, `velocity`, `crust`, `elevation`,
+/// `temperature`, `precipitation` and `biome`.
+pub fn standard_pipeline() -> Pipeline {
+    let mut pipeline = Pipeline::new();
+    pipeline
+        .push(Mesh)
+        .push(Plates::default())
+        .push(Drift::default())
+        .push(Tectonics::default())
+        .push(Noise::default())
+        .push(Erosion::default())
+        .push(Climate::default())
+        .push(Biomes);
+    pipeline
+}
+
+/// Rebuilds the cell mesh from the planet's parameters, clearing its fields
+/// and plates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mesh;
+impl Stage for Mesh {
+    fn name(&self) -> &str {
+        "mesh"
+    }
+    fn rebuilds_planet(&self) -> bool {
+        true
+    }
+    fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+        *planet = PlanetData::new(planet.params);
+        Ok(())
+    }
+}
+
+/// Adds fractal noise to `elevation`, for detail smaller than the plates.
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    /// Largest offset, as a fraction of the radius.
+    pub amplitude: f32,
+    /// Features across the lowest octave, per radian.
+    pub frequency: f32,
+    pub octaves: u32,
+}
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            amplitude: 0.003,
+            frequency: 2.0,
+            octaves: 5,
+        }
+    }
+}
+impl Stage for Noise {
+    fn name(&self) -> &str {
+        "noise"
+    }
+    fn inputs(&self) -> &[&str] {
+        &["elevation"]
+    }
+    fn outputs(&self) -> &[&str] {
+        &["elevation"]
+    }
+    fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+        let sphere = &planet.sphere;
+        let radius = sphere.get_radius();
+        let seed = Rng::stream(planet.params.seed, self.name()).next_u64();
+        let elevation = field::<f32>(planet, "elevation")?;
+        let elevation = map_cells(sphere.num_cells(), |cell| {
+            let dir = sphere.get_position(cell) / radius;
+            let noise = fractal_noise(seed, dir * self.frequency, self.octaves);
+            elevation[cell] + self.amplitude * noise * radius
+        });
+        planet.insert("elevation", Field::new(elevation));
+        Ok(())
+    }
+}
+
+// field the stage can't run without
+fn field<'a, T: FieldValue>(planet: &'a PlanetData, name: &str) -> Result<&'a Field<T>> {
+    planet
+        .get(name)
+        .with_context(|| format!("The planet has no field {} of the right kind", name))
+}
+
+/// Sum of `octaves` layers of value noise, each at twice the frequency and
+/// half the amplitude of the last, roughly in `[-1, 1]`.
+pub fn fractal_noise(seed: u64, pos: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    for octave in 0..octaves {
+        sum += amplitude * value_noise(seed.wrapping_add(octave as u64), pos * frequency);
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum
+}
+
+/// Smoothly interpolated random values on the integer lattice, in `[-1, 1]`.
+pub fn value_noise(seed: u64, pos: Vec3) -> f32 {
+    let base = pos.floor();
+    let t = pos - base;
+    // smoothstep so the gradient is continuous across lattice cells
+    let t = t * t * (Vec3::splat(3.0) - 2.0 * t);
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        let [x, y, z] = base.to_array().map(|c| c as i64);
+        let hash = mix(seed ^ mix((x + dx) as u64 ^ mix((y + dy) as u64 ^ mix((z + dz) as u64))));
+        (hash >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), t.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), t.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), t.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), t.x);
+    lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+}
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use glam::Vec3;
+
+use super::field;
+use crate::{
+    coords,
+    field::Field,
+    icosphere::Icosphere,
+    parallel::map_cells,
+    pipeline::{Stage, StageContext},
+    planet::{PlanetData, Plate},
+    rng::Rng,
+    Result,
+};
+
+// elevations as fractions of the radius, exaggerated well beyond earth's so
+// relief shows on whole-planet renders
+pub(super) const OCEAN_FLOOR: f32 = -0.008;
+pub(super) const CONTINENT: f32 = 0.002;
+
+/// Grows `params.plates` plates from random cells and gives each a rotation
+/// and a crust, adding the fields `plate`, `velocity` and `crust`.
+#[derive(Debug, Clone, Copy)]
+pub struct Plates {
+    /// Share of plates carrying continents.
+    pub continental: f32,
+}
+impl Default for Plates {
+    fn default() -> Self {
+        Plates { continental: 0.35 }
+    }
+}
+impl Stage for Plates {
+    fn name(&self) -> &str {
+        "plates"
+    }
+    fn outputs(&self) -> &[&str] {
+        &["plate", "velocity", "crust"]
+    }
+    fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+        let mut rng = Rng::stream(planet.params.seed, self.name());
+        let sphere = &planet.sphere;
+        let radius = sphere.get_radius();
+
+        let count = (planet.params.plates.max(1) as usize).min(sphere.num_cells());
+        let ids = grow_plates(sphere, count, &mut rng);
+        let plates: Vec<Plate> = (0..count)
+            .map(|_| Plate {
+                axis: rng.unit_vec3(),
+                angular_speed: rng.range(0.2, 1.0),
+            })
+            .collect();
+        let crust: Vec<f32> = (0..count)
+            .map(|_| {
+                if rng.chance(self.continental) {
+                    CONTINENT * radius
+                } else {
+                    OCEAN_FLOOR * radius
+                }
+            })
+            .collect();
+        let velocity = map_cells(sphere.num_cells(), |cell| {
+            plates[ids[cell] as usize].velocity(sphere.get_position(cell))
+        });
+        let crust = ids.iter().map(|&id| crust[id as usize]).collect();
+
+        planet.plates = plates;
+        planet.insert("plate", Field::new(ids));
+        planet.insert("velocity", Field::new(velocity));
+        planet.insert("crust", Field::new(crust));
+        Ok(())
+    }
+}
+
+/// Sets `elevation` to the crust raised into mountains where plates converge
+/// and lowered into rifts where they pull apart.
+#[derive(Debug, Clone, Copy)]
+pub struct Tectonics {
+    /// Height of the strongest collisions, as a fraction of the radius.
+    pub mountains: f32,
+    /// Depth of the strongest rifts, as a fraction of the radius.
+    pub rifts: f32,
+    /// Angle in radians over which a boundary raises or lowers the land.
+    pub boundary_width: f32,
+}
+impl Default for Tectonics {
+    fn default() -> Self {
+        Tectonics {
+            mountains: 0.006,
+            rifts: 0.002,
+            boundary_width: 0.08,
+        }
+    }
+}
+impl Stage for Tectonics {
+    fn name(&self) -> &str {
+        "tectonics"
+    }
+    fn inputs(&self) -> &[&str] {
+        &["plate", "velocity", "crust"]
+    }
+    fn outputs(&self) -> &[&str] {
+        &["elevation"]
+    }
+    fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+        let sphere = &planet.sphere;
+        let radius = sphere.get_radius();
+        let ids = field::<u32>(planet, "plate")?;
+        let velocity = field::<Vec3>(planet, "velocity")?;
+        let crust = field::<f32>(planet, "crust")?;
+
+        let collision = collisions(sphere, ids, velocity, self.boundary_width);
+        let elevation = map_cells(sphere.num_cells(), |cell| {
+            let uplift = if collision[cell] > 0.0 {
+                self.mountains * collision[cell].min(1.5)
+            } else {
+                self.rifts * collision[cell].max(-1.5)
+            };
+            crust[cell] + uplift * radius
+        });
+        planet.insert("elevation", Field::new(elevation));
+        Ok(())
+    }
+}
+
+/// Assign every cell to one of `count` plates grown outwards from random
+/// cells, taking frontier cells in random order so plates get ragged edges.
+pub fn grow_plates(sphere: &Icosphere, count: usize, rng: &mut Rng) -> Vec<u32> {
+    let mut ids = vec![u32::MAX; sphere.num_cells()];
+    let mut frontier = Vec::new();
+    let mut plate = 0;
+    while plate < count as u32 {
+        let cell = rng.below(sphere.num_cells());
+        if ids[cell] == u32::MAX {
+            ids[cell] = plate;
+            frontier.push(cell);
+            plate += 1;
+        }
+    }
+    while !frontier.is_empty() {
+        let cell = frontier.swap_remove(rng.below(frontier.len()));
+        for &n in sphere.get_neighbors(cell) {
+            if ids[n] == u32::MAX {
+                ids[n] = ids[cell];
+                frontier.push(n);
+            }
+        }
+    }
+    ids
+}
+
+// how strongly each cell is pushed together (positive) or pulled apart
+// (negative) by the plate boundary nearest to it, fading with distance
+fn collisions(sphere: &Icosphere, ids: &[u32], velocity: &[Vec3], width: f32) -> Vec<f32> {
+    let radius = sphere.get_radius();
+    let boundary = map_cells(sphere.num_cells(), |cell| {
+        let pos = sphere.get_position(cell);
+        let mut sum = 0.0;
+        let mut across = 0;
+        for &n in sphere.get_neighbors(cell) {
+            if ids[n] != ids[cell] {
+                // closing speed along the line between the cells, in radians
+                // per unit time
+                let dir = (sphere.get_position(n) - pos).normalize();
+                sum += (velocity[cell] - velocity[n]).dot(dir) / radius;
+                across += 1;
+            }
+        }
+        (across > 0).then(|| sum / across as f32)
+    });
+    let mut strength = vec![0.0; sphere.num_cells()];
+    let mut hops = vec![usize::MAX; sphere.num_cells()];
+    let mut queue = VecDeque::new();
+    for (cell, closing) in boundary.into_iter().enumerate() {
+        if let Some(closing) = closing {
+            strength[cell] = closing;
+            hops[cell] = 0;
+            queue.push_back(cell);
+        }
+    }
+
+    // carry each boundary's strength inwards, breadth first
+    while let Some(cell) = queue.pop_front() {
+        for &n in sphere.get_neighbors(cell) {
+            if hops[n] == usize::MAX {
+                hops[n] = hops[cell] + 1;
+                strength[n] = strength[cell];
+                queue.push_back(n);
+            }
+        }
+    }
+
+    let step = match sphere.get_neighbors(0).first() {
+        Some(&n) => coords::angle_between(sphere.get_position(0), sphere.get_position(n)),
+        None => return strength,
+    };
+    map_cells(sphere.num_cells(), |cell| {
+        if hops[cell] == usize::MAX {
+            return 0.0;
+        }
+        let distance = hops[cell] as f32 * step / width;
+        strength[cell] * libm::expf(-distance * distance)
+    })
+}
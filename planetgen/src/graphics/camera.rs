@@ -1,3 +1,11 @@
+use glam::Vec3;
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Camera {
     pub eye: glam::Vec3,
     pub target: glam::Vec3,
@@ -8,12 +16,31 @@ pub struct Camera {
     pub zfar: f32,
 }
 
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            // position the camera one unit up and 2 units back
+            // +z is out of the screen
+            eye: (0.0, 1.0, 2.0).into(),
+            // have it look at the origin
+            target: (0.0, 0.0, 0.0).into(),
+            // which way is "up"
+            up: glam::Vec3::Y,
+            aspect: 1.0,
+            fov: f32::to_radians(45.0),
+            // close enough to fly down to the surface of a unit planet
+            znear: 0.01,
+            zfar: 100.0,
+        }
+    }
+}
+
 impl Camera {
     fn build_view_projection_matrix(&self) -> glam::Mat4 {
         let view = glam::Mat4::look_at_rh(self.eye, self.target, self.up);
         let proj = glam::Mat4::perspective_rh(self.fov, self.aspect, self.znear, self.zfar);
 
-        return proj * view;
+        proj * view
     }
 }
 
@@ -36,3 +63,311 @@ impl CameraUniform {
         self.view_proj = camera.build_view_projection_matrix().to_cols_array_2d();
     }
 }
+
+// nearest the camera gets to the surface, above the near plane
+const MIN_ALTITUDE: f32 = 0.02;
+// radians turned per pixel dragged, far from the surface
+const TURN_SPEED: f32 = 0.005;
+// steepest look up or down, short of straight up where yaw is undefined
+const MAX_PITCH: f32 = 1.5;
+
+/// How the mouse and keyboard move the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Drag to turn around the planet's centre and scroll to zoom.
+    Orbit,
+    /// Drag to look around and move with W, A, S, D, and Q and E for down
+    /// and up.
+    Fly,
+}
+
+/// Camera moved by mouse and keyboard input around a planet centred on the
+/// origin, never going below its surface. F switches between orbiting and
+/// flying.
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    mode: CameraMode,
+    // radius of the highest point of the planet
+    surface: f32,
+    // orbit: angles around the y axis and up from the equator, and distance
+    // from the centre
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    // fly: position and the angles looked towards
+    eye: Vec3,
+    look_yaw: f32,
+    look_pitch: f32,
+    dragging: bool,
+    cursor: Option<(f64, f64)>,
+    // keys held for flying: forwards, right and up
+    forward: [bool; 2],
+    right: [bool; 2],
+    up: [bool; 2],
+}
+impl Default for CameraController {
+    fn default() -> Self {
+        let mut controller = CameraController {
+            mode: CameraMode::Orbit,
+            surface: 1.0,
+            yaw: 0.0,
+            pitch: 0.45,
+            distance: 3.0,
+            eye: Vec3::ZERO,
+            look_yaw: 0.0,
+            look_pitch: 0.0,
+            dragging: false,
+            cursor: None,
+            forward: [false; 2],
+            right: [false; 2],
+            up: [false; 2],
+        };
+        controller.eye = controller.orbit_eye();
+        controller
+    }
+}
+impl CameraController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switch between orbiting and flying, starting from where the camera
+    /// is and looking the same way.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        match (self.mode, mode) {
+            (CameraMode::Orbit, CameraMode::Fly) => {
+                self.eye = self.orbit_eye();
+                let look = -self.eye.normalize();
+                self.look_yaw = libm::atan2f(look.x, look.z);
+                self.look_pitch = libm::asinf(look.y);
+            }
+            (CameraMode::Fly, CameraMode::Orbit) => {
+                self.distance = self.eye.length();
+                self.yaw = libm::atan2f(self.eye.x, self.eye.z);
+                self.pitch = libm::asinf(self.eye.y / self.distance).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+            _ => {}
+        }
+        self.mode = mode;
+    }
+
+    /// Keep the camera above `radius`, the highest point of the planet.
+    pub fn set_surface(&mut self, radius: f32) {
+        self.surface = radius;
+        self.distance = self.distance.max(radius + MIN_ALTITUDE);
+        self.keep_above_surface();
+    }
+
+    /// Where the camera is, in either mode.
+    pub fn get_eye(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Orbit => self.orbit_eye(),
+            CameraMode::Fly => self.eye,
+        }
+    }
+
+    /// Take the input the camera responds to, returning whether it did.
+    pub fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = *state == ElementState::Pressed;
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let (x, y) = (position.x, position.y);
+                if let (true, Some((last_x, last_y))) = (self.dragging, self.cursor) {
+                    self.turn((x - last_x) as f32, (y - last_y) as f32);
+                }
+                self.cursor = Some((x, y));
+                self.dragging
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                self.zoom(lines);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                match key {
+                    VirtualKeyCode::F if pressed => {
+                        self.set_mode(match self.mode {
+                            CameraMode::Orbit => CameraMode::Fly,
+                            CameraMode::Fly => CameraMode::Orbit,
+                        });
+                    }
+                    VirtualKeyCode::W => self.forward[0] = pressed,
+                    VirtualKeyCode::S => self.forward[1] = pressed,
+                    VirtualKeyCode::D => self.right[0] = pressed,
+                    VirtualKeyCode::A => self.right[1] = pressed,
+                    VirtualKeyCode::E => self.up[0] = pressed,
+                    VirtualKeyCode::Q => self.up[1] = pressed,
+                    _ => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Fly on by the keys held for `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        if self.mode != CameraMode::Fly {
+            return;
+        }
+        let axis = |[plus, minus]: [bool; 2]| plus as i32 as f32 - minus as i32 as f32;
+        let (forward, right) = self.look_axes();
+        let velocity =
+            forward * axis(self.forward) + right * axis(self.right) + Vec3::Y * axis(self.up);
+        // cover the height above the surface in a second, slowing down
+        // to land
+        let speed = self.eye.length() - self.surface;
+        self.eye += velocity.normalize_or_zero() * speed * dt;
+        self.keep_above_surface();
+    }
+
+    /// Point `camera` where the controller has moved it.
+    pub fn update_camera(&self, camera: &mut Camera) {
+        match self.mode {
+            CameraMode::Orbit => {
+                camera.eye = self.orbit_eye();
+                camera.target = Vec3::ZERO;
+            }
+            CameraMode::Fly => {
+                camera.eye = self.eye;
+                camera.target = self.eye + self.look_axes().0;
+            }
+        }
+        camera.up = Vec3::Y;
+    }
+
+    fn orbit_eye(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = (libm::sinf(self.yaw), libm::cosf(self.yaw));
+        let (sin_pitch, cos_pitch) = (libm::sinf(self.pitch), libm::cosf(self.pitch));
+        Vec3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw) * self.distance
+    }
+
+    // unit vectors looked along and to the right when flying
+    fn look_axes(&self) -> (Vec3, Vec3) {
+        let (sin_yaw, cos_yaw) = (libm::sinf(self.look_yaw), libm::cosf(self.look_yaw));
+        let cos_pitch = libm::cosf(self.look_pitch);
+        let forward = Vec3::new(
+            cos_pitch * sin_yaw,
+            libm::sinf(self.look_pitch),
+            cos_pitch * cos_yaw,
+        );
+        (forward, forward.cross(Vec3::Y).normalize_or_zero())
+    }
+
+    // drag by `dx` and `dy` pixels, turning slower near the surface so the
+    // ground under the cursor keeps up
+    fn turn(&mut self, dx: f32, dy: f32) {
+        match self.mode {
+            CameraMode::Orbit => {
+                let speed = TURN_SPEED * ((self.distance - self.surface) / self.distance).min(1.0);
+                self.yaw -= dx * speed;
+                self.pitch = (self.pitch + dy * speed).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+            CameraMode::Fly => {
+                self.look_yaw -= dx * TURN_SPEED;
+                self.look_pitch = (self.look_pitch - dy * TURN_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+            }
+        }
+    }
+
+    // scroll by `lines`, positive towards the planet, each line taking a
+    // tenth off the height above the surface
+    fn zoom(&mut self, lines: f32) {
+        match self.mode {
+            CameraMode::Orbit => {
+                let altitude = (self.distance - self.surface) * libm::powf(0.9, lines);
+                self.distance = self.surface + altitude.max(MIN_ALTITUDE);
+            }
+            CameraMode::Fly => {
+                let altitude = self.eye.length() - self.surface;
+                self.eye += self.look_axes().0 * altitude * 0.1 * lines;
+                self.keep_above_surface();
+            }
+        }
+    }
+
+    fn keep_above_surface(&mut self) {
+        let lowest = self.surface + MIN_ALTITUDE;
+        if self.eye.length() < lowest {
+            self.eye = self.eye.normalize_or_zero() * lowest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-5, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn zooming_stops_above_the_surface() {
+        let mut controller = CameraController::new();
+        controller.set_surface(1.2);
+        controller.zoom(1000.0);
+        assert!((controller.distance - (1.2 + MIN_ALTITUDE)).abs() < 1e-6);
+        controller.zoom(-10.0);
+        assert!(controller.distance > 1.2 + MIN_ALTITUDE);
+    }
+
+    #[test]
+    fn switching_modes_keeps_the_eye() {
+        let mut controller = CameraController::new();
+        controller.turn(120.0, -40.0);
+        let eye = controller.get_eye();
+        controller.set_mode(CameraMode::Fly);
+        assert_near(controller.get_eye(), eye);
+        // still looking at the planet's centre
+        assert_near(controller.look_axes().0, -eye.normalize());
+        controller.set_mode(CameraMode::Orbit);
+        assert_near(controller.get_eye(), eye);
+
+        // and from wherever the flight went
+        controller.set_mode(CameraMode::Fly);
+        controller.right[0] = true;
+        controller.update(0.5);
+        let flown = controller.get_eye();
+        assert!(flown.distance(eye) > 0.1);
+        controller.set_mode(CameraMode::Orbit);
+        assert_near(controller.get_eye(), flown);
+    }
+
+    #[test]
+    fn flying_stays_above_the_surface() {
+        let mut controller = CameraController::new();
+        controller.set_surface(1.1);
+        controller.set_mode(CameraMode::Fly);
+        // straight at the planet, in frames long enough to pass the surface
+        controller.forward[0] = true;
+        for _ in 0..10 {
+            controller.update(1.2);
+            assert!(controller.get_eye().length() >= 1.1 + MIN_ALTITUDE - 1e-6);
+        }
+        controller.zoom(100.0);
+        assert!(controller.get_eye().length() >= 1.1 + MIN_ALTITUDE - 1e-6);
+        // raising the surface under the camera lifts it too
+        controller.set_surface(1.5);
+        assert!(controller.get_eye().length() >= 1.5 + MIN_ALTITUDE - 1e-6);
+    }
+}
@@ -1,9 +1,9 @@
-mod camera;
+pub mod camera;
 pub mod engine;
-mod texture;
+mod renderer;
 // mod model;
 
-pub use texture::*;
+use planetgen::export::mesh::MeshData;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -39,34 +39,15 @@ impl Vertex {
     }
 }
 
-pub type Index = u16;
+pub type Index = u32;
 
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-0.0868241, 0.49240386, 0.0],
-        tex_coords: [0.4131759, 0.00759614],
-        color: [1.0, 0.0, 0.5],
-    }, // A
-    Vertex {
-        position: [-0.49513406, 0.06958647, 0.0],
-        tex_coords: [0.0048659444, 0.43041354],
-        color: [0.8, 0.2, 0.5],
-    }, // B
-    Vertex {
-        position: [-0.21918549, -0.44939706, 0.0],
-        tex_coords: [0.28081453, 0.949397],
-        color: [0.6, 0.4, 0.5],
-    }, // C
-    Vertex {
-        position: [0.35966998, -0.3473291, 0.0],
-        tex_coords: [0.85967, 0.84732914],
-        color: [0.4, 0.6, 0.5],
-    }, // D
-    Vertex {
-        position: [0.44147372, 0.2347359, 0.0],
-        tex_coords: [0.9414737, 0.2652641],
-        color: [0.2, 0.8, 0.5],
-    }, // E
-];
-
-const INDICES: &[Index] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, /* padding */ 0];
+/// Vertices of a mesh to upload to the GPU, black where it has no colours.
+pub fn vertices(mesh: &MeshData) -> Vec<Vertex> {
+    (0..mesh.num_vertices())
+        .map(|i| Vertex {
+            position: mesh.positions[i],
+            tex_coords: mesh.tex_coords.as_ref().map_or([0.0; 2], |t| t[i]),
+            color: mesh.colors.as_ref().map_or([0.0; 3], |c| c[i]),
+        })
+        .collect()
+}
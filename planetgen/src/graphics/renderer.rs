@@ -0,0 +1,400 @@
+use super::camera::{Camera, CameraUniform};
+use super::*;
+
+use wgpu::util::DeviceExt;
+
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+const BACKGROUND: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Draws a mesh with a depth buffer into any texture of one format, the
+/// window's surface or an offscreen one.
+pub struct Renderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    render_pipeline: wgpu::RenderPipeline,
+    depth_view: wgpu::TextureView,
+    // vertex and index buffers and the number of indices, once there is a
+    // mesh to draw
+    mesh: Option<(wgpu::Buffer, wgpu::Buffer, u32)>,
+    camera_uniform: CameraUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    transform_buffer: wgpu::Buffer,
+    transform_bind_group: wgpu::BindGroup,
+}
+impl Renderer {
+    /// Render to `width` by `height` textures of `format`.
+    pub fn new(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        let camera_uniform = CameraUniform::new();
+
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        let transform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("transform_bind_group_layout"),
+            });
+
+        let transform: [[f32; 4]; 4] = glam::Mat4::from_rotation_y(0.0).to_cols_array_2d();
+        let transform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Transform Buffer"),
+            contents: bytemuck::cast_slice(&[transform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let transform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &transform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: transform_buffer.as_entire_binding(),
+            }],
+            label: Some("transform_bind_group"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &transform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent::REPLACE,
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // the planet's triangles wind counter-clockwise seen from outside
+                cull_mode: Some(wgpu::Face::Back),
+                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+                // or Features::POLYGON_MODE_POINT
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // If the pipeline will be used with a multiview render pass, this
+            // indicates how many array layers the attachments will have.
+            multiview: None,
+        });
+
+        Renderer {
+            depth_view: create_depth_view(&device, width, height),
+            device,
+            queue,
+            render_pipeline,
+            mesh: None,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            transform_buffer,
+            transform_bind_group,
+        }
+    }
+
+    pub fn get_device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    /// Draw `vertices` and `indices` from now on, in place of the last mesh.
+    pub fn set_mesh(&mut self, vertices: &[Vertex], indices: &[Index]) {
+        let vertex_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+        let index_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+        self.mesh = Some((vertex_buffer, index_buffer, indices.len() as u32));
+    }
+
+    /// Place the mesh in the world.
+    pub fn set_transform(&mut self, transform: glam::Mat4) {
+        let transform: [[f32; 4]; 4] = transform.to_cols_array_2d();
+        self.queue.write_buffer(
+            &self.transform_buffer,
+            0,
+            bytemuck::cast_slice(&[transform]),
+        );
+    }
+
+    /// Look through `camera`.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera_uniform.update_view_proj(camera);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+    }
+
+    /// Size the depth buffer to match render targets `width` by `height`.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.depth_view = create_depth_view(&self.device, width, height);
+    }
+
+    /// Draw the mesh into `view`, which must be the size last given to
+    /// [`Renderer::resize`].
+    pub fn draw(&self, view: &wgpu::TextureView) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(BACKGROUND),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            if let Some((vertex_buffer, index_buffer, num_indices)) = &self.mesh {
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.transform_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..*num_indices, 0, 0..1);
+            }
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+fn create_depth_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Depth Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a red square in front of a green one filling the view, drawn first so
+    // only the depth test keeps it visible, and a blue triangle nearer still
+    // facing away from the camera
+    fn scene() -> (Vec<Vertex>, Vec<Index>) {
+        let vertex = |x: f32, y: f32, z: f32, color: [f32; 3]| Vertex {
+            position: [x, y, z],
+            tex_coords: [0.0; 2],
+            color,
+        };
+        let square = |size: f32, z: f32, color| {
+            [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(x, y)| vertex(x * size, y * size, z, color))
+        };
+        let mut vertices = Vec::new();
+        vertices.extend(square(0.5, 0.5, [1.0, 0.0, 0.0]));
+        vertices.extend(square(3.0, -1.0, [0.0, 1.0, 0.0]));
+        vertices.extend([
+            vertex(-1.0, -1.0, 1.0, [0.0, 0.0, 1.0]),
+            vertex(0.0, 1.0, 1.0, [0.0, 0.0, 1.0]),
+            vertex(1.0, -1.0, 1.0, [0.0, 0.0, 1.0]),
+        ]);
+        let indices = vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7, 8, 9, 10];
+        (vertices, indices)
+    }
+
+    // render a square image `size` pixels wide and read it back
+    fn render(renderer: &mut Renderer, size: u32) -> Vec<[u8; 4]> {
+        renderer.resize(size, size);
+        let device = &renderer.device;
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (size * size * 4) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        renderer.draw(&texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    // rows must be a multiple of 256 bytes
+                    bytes_per_row: std::num::NonZeroU32::new(size * 4),
+                    rows_per_image: None,
+                },
+            },
+            extent,
+        );
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let pixels = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        pixels
+    }
+
+    #[test]
+    fn nearer_triangles_hide_further_ones() {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        // a software adapter such as llvmpipe is enough
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+            .expect("no GPU adapter to render with");
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_webgl2_defaults(),
+            },
+            None,
+        ))
+        .unwrap();
+        let mut renderer = Renderer::new(device, queue, wgpu::TextureFormat::Rgba8Unorm, 64, 64);
+        renderer.set_camera(&Camera {
+            eye: (0.0, 0.0, 3.0).into(),
+            ..Default::default()
+        });
+        let (vertices, indices) = scene();
+        renderer.set_mesh(&vertices, &indices);
+
+        // again after resizing, which must replace the depth buffer
+        for size in [64, 128] {
+            let pixels = render(&mut renderer, size);
+            let pixel = |x: u32, y: u32| pixels[(y * size + x) as usize];
+            assert_eq!(
+                pixel(size / 2, size / 2),
+                [255, 0, 0, 255],
+                "{} pixels",
+                size
+            );
+            assert_eq!(pixel(2, 2), [0, 255, 0, 255], "{} pixels", size);
+        }
+    }
+}
@@ -2,10 +2,10 @@
 struct CameraUniform {
     view_proj: mat4x4<f32>,
 };
-@group(1) @binding(0)
+@group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
-@group(2) @binding(0)
+@group(1) @binding(0)
 var<uniform> transform: mat4x4<f32>;
 
 struct VertexInput {
@@ -32,12 +32,7 @@ fn vs_main(
 }
 
 // Fragment shader
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0)@binding(1)
-var s_diffuse: sampler;
-
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return in.vert_color + textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return in.vert_color;
 }
\ No newline at end of file
@@ -1,34 +1,73 @@
-use glam::Vec3;
-use std::array;
+use glam::{DVec3, Vec3};
+use std::{array, collections::HashMap};
 
-#[derive(Debug)]
-struct Point {
-    index: u16,
-    pos: Vec3,
+use crate::parallel::{for_each_mut, map_cells};
+
+#[derive(Debug, Clone)]
+pub struct Point {
+    index: usize,
+    pos: DVec3,
+}
+impl Point {
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+    pub fn get_pos(&self) -> Vec3 {
+        self.pos.as_vec3()
+    }
+    pub fn get_pos_f64(&self) -> DVec3 {
+        self.pos
+    }
 }
 
-#[derive(Debug)]
-struct Face {
-    corners: [Point; 3],
+#[derive(Debug, Clone)]
+pub struct Face {
+    corners: [usize; 3],
+}
+impl Face {
+    pub fn get_corners(&self) -> [usize; 3] {
+        self.corners
+    }
 }
 
-struct Icosphere {
+/// A subdivided icosahedron projected onto a sphere.
+///
+/// Every vertex of the mesh is the centre of one cell of the planet, so cell
+/// ids and vertex indices are interchangeable.
+///
+/// Positions are kept in `f64`, which resolves well under a millimetre on an
+/// earth-sized sphere in metres, and the `f32` accessors round them. To
+/// render at such scales take positions relative to the camera with
+/// [`Icosphere::get_relative_positions`].
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "Mesh", try_from = "Mesh")
+)]
+pub struct Icosphere {
+    radius: f64,
     vertices: Vec<Point>,
-    // faces: [Face; 20],
+    faces: Vec<Face>,
+    // neighbouring cells of each cell, sorted counter-clockwise around it
+    neighbors: Vec<Vec<usize>>,
 }
 impl Icosphere {
-    fn new(radius: f32) -> Self {
+    pub fn new(radius: f32) -> Self {
+        Icosphere::new_f64(radius as f64)
+    }
+    pub fn new_f64(radius: f64) -> Self {
         // ensure we have a positive radius
         assert!(radius > 0.0);
 
         // calculate latitude and longitude angles
-        let lat_angle = f32::atan(0.5);
-        let long_angle = f32::to_radians(36.0);
+        let lat_angle = libm::atan(0.5);
+        let long_angle = f64::to_radians(36.0);
 
         // top ring is the opposite side of a triangle
         // with hypotenuse radius and angle latitude_angle
-        let top_ring_height = radius * lat_angle.sin();
-        let top_ring_radius = radius * lat_angle.cos();
+        let top_ring_height = radius * libm::sin(lat_angle);
+        let top_ring_radius = radius * libm::cos(lat_angle);
 
         // define inital icosahedron vertex positions
         let vertices: [Point; 12] = array::from_fn(|i| {
@@ -39,21 +78,290 @@ impl Icosphere {
                 11 => [0.0, 0.0, -radius],
                 // top ring
                 1..=5 => [
-                    top_ring_radius * ((i - 1) as f32 * 2.0 * long_angle).cos(),
-                    top_ring_radius * ((i - 1) as f32 * 2.0 * long_angle).sin(),
+                    top_ring_radius * libm::cos((i - 1) as f64 * 2.0 * long_angle),
+                    top_ring_radius * libm::sin((i - 1) as f64 * 2.0 * long_angle),
                     top_ring_height,
                 ],
                 // bottom ring
                 6..=10 => [
-                    top_ring_radius * (((i - 6) as f32 * 2.0 - 1.0) * long_angle).cos(),
-                    top_ring_radius * (((i - 6) as f32 * 2.0 - 1.0) * long_angle).sin(),
+                    top_ring_radius * libm::cos(((i - 6) as f64 * 2.0 - 1.0) * long_angle),
+                    top_ring_radius * libm::sin(((i - 6) as f64 * 2.0 - 1.0) * long_angle),
                     -top_ring_height,
                 ],
                 _ => panic!("Invalid number of vertices for Icosahedron"),
             };
-            Point { index: i as u16, pos: pos.into() }
+            Point {
+                index: i,
+                pos: pos.into(),
+            }
+        });
+
+        // same faces as geometry::Icosahedron
+        let corners: [[usize; 3]; 20] = [
+            // top faces
+            [2, 1, 0],
+            [3, 2, 0],
+            [4, 3, 0],
+            [5, 4, 0],
+            [1, 5, 0],
+            // ring faces
+            [7, 6, 1],
+            [7, 1, 2],
+            [8, 7, 2],
+            [8, 2, 3],
+            [9, 8, 3],
+            [9, 3, 4],
+            [10, 9, 4],
+            [10, 4, 5],
+            [6, 10, 5],
+            [6, 5, 1],
+            // bottom faces
+            [6, 7, 11],
+            [7, 8, 11],
+            [8, 9, 11],
+            [9, 10, 11],
+            [10, 6, 11],
+        ];
+
+        let mut sphere = Icosphere {
+            radius,
+            vertices: vertices.into(),
+            faces: Vec::new(),
+            neighbors: Vec::new(),
+        };
+        sphere.faces = corners.map(|c| sphere.oriented_face(c)).into();
+        sphere.build_neighbors();
+        sphere
+    }
+
+    /// Create an icosphere and subdivide it `subdivisions` times.
+    pub fn subdivided(radius: f32, subdivisions: u32) -> Self {
+        Icosphere::subdivided_f64(radius as f64, subdivisions)
+    }
+    pub fn subdivided_f64(radius: f64, subdivisions: u32) -> Self {
+        let mut sphere = Icosphere::new_f64(radius);
+        for _ in 0..subdivisions {
+            sphere.subdivide();
+        }
+        sphere
+    }
+
+    /// Rebuild a sphere from its vertex positions and counter-clockwise
+    /// wound faces, as returned by `get_vertices` and `get_faces`.
+    pub fn from_mesh(radius: f32, positions: Vec<Vec3>, faces: Vec<[usize; 3]>) -> Self {
+        let positions = positions.iter().map(Vec3::as_dvec3).collect();
+        Icosphere::from_mesh_f64(radius as f64, positions, faces)
+    }
+    pub fn from_mesh_f64(radius: f64, positions: Vec<DVec3>, faces: Vec<[usize; 3]>) -> Self {
+        assert!(radius > 0.0);
+        let mut sphere = Icosphere {
+            radius,
+            vertices: positions
+                .into_iter()
+                .enumerate()
+                .map(|(index, pos)| Point { index, pos })
+                .collect(),
+            faces: faces.into_iter().map(|corners| Face { corners }).collect(),
+            neighbors: Vec::new(),
+        };
+        sphere.build_neighbors();
+        sphere
+    }
+
+    /// Split every face into four, projecting the new vertices onto the sphere.
+    ///
+    /// Existing vertices keep their indices. Returns the two vertices whose
+    /// edge each new vertex split, in the order they were added.
+    pub fn subdivide(&mut self) -> Vec<[usize; 2]> {
+        let mut midpoints = HashMap::<(usize, usize), usize>::new();
+        let mut parents = Vec::new();
+        let mut faces = Vec::with_capacity(self.faces.len() * 4);
+
+        for face in &self.faces {
+            let [a, b, c] = face.corners;
+            let mut midpoint = |i: usize, j: usize| {
+                *midpoints.entry((i.min(j), i.max(j))).or_insert_with(|| {
+                    let index = self.vertices.len();
+                    let pos =
+                        (self.vertices[i].pos + self.vertices[j].pos).normalize() * self.radius;
+                    self.vertices.push(Point { index, pos });
+                    parents.push([i, j]);
+                    index
+                })
+            };
+            let ab = midpoint(a, b);
+            let bc = midpoint(b, c);
+            let ca = midpoint(c, a);
+
+            // keep the winding of the parent face
+            faces.push(Face {
+                corners: [a, ab, ca],
+            });
+            faces.push(Face {
+                corners: [b, bc, ab],
+            });
+            faces.push(Face {
+                corners: [c, ca, bc],
+            });
+            faces.push(Face {
+                corners: [ab, bc, ca],
+            });
+        }
+
+        self.faces = faces;
+        self.build_neighbors();
+        parents
+    }
+
+    pub fn get_radius(&self) -> f32 {
+        self.radius as f32
+    }
+    pub fn get_radius_f64(&self) -> f64 {
+        self.radius
+    }
+    pub fn get_vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+    pub fn get_faces(&self) -> &[Face] {
+        &self.faces
+    }
+    pub fn num_cells(&self) -> usize {
+        self.vertices.len()
+    }
+    /// Position of the centre of `cell`.
+    pub fn get_position(&self, cell: usize) -> Vec3 {
+        self.vertices[cell].pos.as_vec3()
+    }
+    pub fn get_position_f64(&self, cell: usize) -> DVec3 {
+        self.vertices[cell].pos
+    }
+    /// Cells sharing an edge with `cell`, in counter-clockwise order seen from outside.
+    pub fn get_neighbors(&self, cell: usize) -> &[usize] {
+        &self.neighbors[cell]
+    }
+
+    /// Position of `cell` raised by `elevation` above the sphere.
+    pub fn get_displaced_position(&self, cell: usize, elevation: f32) -> Vec3 {
+        self.get_displaced_position_f64(cell, elevation as f64)
+            .as_vec3()
+    }
+    pub fn get_displaced_position_f64(&self, cell: usize, elevation: f64) -> DVec3 {
+        let pos = self.vertices[cell].pos;
+        pos * ((self.radius + elevation) / self.radius)
+    }
+
+    /// Displaced position of every cell relative to `origin`, such as the
+    /// camera position. Subtracting in `f64` before rounding keeps detail
+    /// near the origin exact however large the planet is.
+    pub fn get_relative_positions(&self, elevation: &[f32], origin: DVec3) -> Vec<Vec3> {
+        assert_eq!(elevation.len(), self.vertices.len());
+        map_cells(self.vertices.len(), |cell| {
+            (self.get_displaced_position_f64(cell, elevation[cell] as f64) - origin).as_vec3()
+        })
+    }
+
+    /// Unit normal at each vertex after raising every cell by its elevation,
+    /// given in the same units as the radius.
+    pub fn get_normals(&self, elevation: &[f32]) -> Vec<Vec3> {
+        assert_eq!(elevation.len(), self.vertices.len());
+        (0..self.vertices.len())
+            .map(|cell| {
+                // sum the normals of the fan of faces around the vertex,
+                // weighted by area through the unnormalized cross product,
+                // with edges taken in f64 so they stay exact on large spheres
+                let displaced = |c: usize| self.get_displaced_position_f64(c, elevation[c] as f64);
+                let centre = displaced(cell);
+                let ring = &self.neighbors[cell];
+                let normal = (0..ring.len()).fold(Vec3::ZERO, |sum, i| {
+                    let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+                    let a = (displaced(a) - centre).as_vec3();
+                    let b = (displaced(b) - centre).as_vec3();
+                    sum + a.cross(b)
+                });
+                normal.normalize()
+            })
+            .collect()
+    }
+
+    // wind the face counter-clockwise when seen from outside the sphere
+    fn oriented_face(&self, corners: [usize; 3]) -> Face {
+        let [a, b, c] = corners.map(|i| self.vertices[i].pos);
+        if (b - a).cross(c - a).dot(a + b + c) < 0.0 {
+            Face {
+                corners: [corners[0], corners[2], corners[1]],
+            }
+        } else {
+            Face { corners }
+        }
+    }
+
+    fn build_neighbors(&mut self) {
+        let mut neighbors = vec![Vec::<usize>::with_capacity(6); self.vertices.len()];
+        for face in &self.faces {
+            let [a, b, c] = face.corners;
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                if !neighbors[from].contains(&to) {
+                    neighbors[from].push(to);
+                }
+                if !neighbors[to].contains(&from) {
+                    neighbors[to].push(from);
+                }
+            }
+        }
+
+        // sort each ring by angle around the cell's normal
+        let vertices = &self.vertices;
+        for_each_mut(&mut neighbors, |cell, ring| {
+            let normal = vertices[cell].pos.normalize();
+            let tangent = normal.any_orthonormal_vector();
+            let bitangent = normal.cross(tangent);
+            let angle = |n: &usize| {
+                let d = vertices[*n].pos - vertices[cell].pos;
+                libm::atan2(d.dot(bitangent), d.dot(tangent))
+            };
+            ring.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
         });
 
-        Icosphere { vertices: vertices.into() }
+        self.neighbors = neighbors;
+    }
+}
+
+// what a serialized sphere holds, the neighbours being rebuilt on load
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Mesh {
+    radius: f64,
+    positions: Vec<DVec3>,
+    faces: Vec<[usize; 3]>,
+}
+#[cfg(feature = "serde")]
+impl From<Icosphere> for Mesh {
+    fn from(sphere: Icosphere) -> Self {
+        Mesh {
+            radius: sphere.radius,
+            positions: sphere.vertices.iter().map(|v| v.pos).collect(),
+            faces: sphere.faces.iter().map(|f| f.corners).collect(),
+        }
+    }
+}
+#[cfg(feature = "serde")]
+impl TryFrom<Mesh> for Icosphere {
+    type Error = String;
+    fn try_from(mesh: Mesh) -> Result<Self, Self::Error> {
+        if mesh.radius <= 0.0 {
+            return Err(format!("radius {} isn't positive", mesh.radius));
+        }
+        let count = mesh.positions.len();
+        if let Some(face) = mesh.faces.iter().find(|f| f.iter().any(|&c| c >= count)) {
+            return Err(format!(
+                "face {:?} has corners past the {} vertices",
+                face, count
+            ));
+        }
+        Ok(Icosphere::from_mesh_f64(
+            mesh.radius,
+            mesh.positions,
+            mesh.faces,
+        ))
     }
 }
@@ -0,0 +1,64 @@
+//! Procedural planet generation on a subdivided icosphere.
+//!
+//! Every vertex of an [`icosphere::Icosphere`] is one cell of the planet, and
+//! per-cell values such as elevation or plate ids are [`field::Field`]s
+//! indexed by cell. [`planet::PlanetData`] bundles the mesh with its named
+//! fields, plates and generation parameters, and can be saved with
+//! [`save::save_planet`].
+//! [`generate::generate`] fills a planet by running the stages of a
+//! [`pipeline::Pipeline`], from plates through erosion to biomes, and custom
+//! stages can be added to or swapped into the pipeline. The [`export`] module
+//! turns a planet into maps, meshes and vector data.
+//!
+//! ```
+//! use planetgen::{
+//!     export::{map::render_scalar, ColorRamp, Interpolation},
+//!     field::Field,
+//!     planet::{GenerationParams, PlanetData},
+//!     projection::Equirectangular,
+//!     coords::CellLocator,
+//! };
+//!
+//! let mut planet = PlanetData::new(GenerationParams {
+//!     subdivisions: 3,
+//!     ..Default::default()
+//! });
+//! let elevation: Vec<f32> = (0..planet.sphere.num_cells())
+//!     .map(|cell| planet.sphere.get_position(cell).z)
+//!     .collect();
+//! let locator = CellLocator::new(&planet.sphere);
+//! let map = render_scalar(
+//!     &locator,
+//!     &elevation,
+//!     &ColorRamp::terrain(-1.0, 0.0, 1.0),
+//!     &Equirectangular::default(),
+//!     64,
+//!     32,
+//!     Interpolation::Barycentric,
+//! );
+//! assert_eq!(map.width(), 64);
+//! planet.insert("elevation", Field::new(elevation));
+//! assert!(planet.get::<f32>("elevation").unwrap().max() <= Some(1.0));
+//! ```
+//!
+//! The windowed viewer is behind the `viewer` feature, so the library can be
+//! used without wgpu or winit by turning off default features.
+
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod coords;
+pub mod export;
+pub mod field;
+pub mod generate;
+pub mod icosphere;
+mod parallel;
+pub mod path;
+pub mod pipeline;
+pub mod planet;
+pub mod projection;
+pub mod rng;
+pub mod save;
+pub mod timeline;
+pub mod vector;
+
+pub type Result<T> = anyhow::Result<T>;
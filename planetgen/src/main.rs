@@ -1,17 +1,32 @@
+//! Command-line generator and windowed viewer for planetgen.
+
+mod cli;
+#[cfg(feature = "viewer")]
 #[allow(dead_code)]
 mod geometry;
+#[cfg(feature = "viewer")]
 mod graphics;
-pub mod window;
-
-mod icosphere;
+#[cfg(feature = "viewer")]
+mod window;
 
-use winit::event_loop::EventLoop;
-
-use window::run;
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    #[cfg(feature = "viewer")]
+    if args.is_empty() || args[0] == "view" {
+        view();
+        return;
+    }
+    if let Err(e) = cli::run(&args) {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
 
-pub type Result<T> = anyhow::Result<T>;
+#[cfg(feature = "viewer")]
+fn view() {
+    use window::run;
+    use winit::event_loop::EventLoop;
 
-fn main() {
     let event_loop = EventLoop::new();
     let window = winit::window::Window::new(&event_loop).unwrap();
     #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,64 @@
+// per-cell loops that run on rayon's thread pool with the `parallel` feature
+// and serially otherwise, including always on wasm32 which has no threads.
+// each item is computed on its own, so both give bit-identical results
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+
+/// `f` of every cell index below `cells`, in order.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+pub(crate) fn map_cells<T: Send>(cells: usize, f: impl Fn(usize) -> T + Sync + Send) -> Vec<T> {
+    (0..cells).into_par_iter().map(f).collect()
+}
+#[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+pub(crate) fn map_cells<T: Send>(cells: usize, f: impl Fn(usize) -> T + Sync + Send) -> Vec<T> {
+    (0..cells).map(f).collect()
+}
+
+/// Call `f` with the index of and a reference to every item.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+pub(crate) fn for_each_mut<T: Send>(items: &mut [T], f: impl Fn(usize, &mut T) + Sync + Send) {
+    items
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(i, item)| f(i, item));
+}
+#[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+pub(crate) fn for_each_mut<T: Send>(items: &mut [T], f: impl Fn(usize, &mut T) + Sync + Send) {
+    items
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, item)| f(i, item));
+}
+
+#[cfg(all(test, feature = "parallel", not(target_arch = "wasm32")))]
+mod tests {
+    use crate::{
+        generate::standard_pipeline,
+        planet::{GenerationParams, PlanetData},
+        save::write_planet,
+    };
+
+    #[test]
+    fn threads_do_not_change_the_planet() {
+        // a pool of one thread runs every loop in order, like the serial build
+        let generate = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut planet = PlanetData::new(GenerationParams {
+                    seed: 5,
+                    subdivisions: 3,
+                    ..Default::default()
+                });
+                standard_pipeline().run(&mut planet).unwrap();
+                let mut bytes = Vec::new();
+                write_planet(&planet, &mut bytes).unwrap();
+                bytes
+            })
+        };
+        assert!(generate(1) == generate(4));
+    }
+}
@@ -0,0 +1,272 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{coords, icosphere::Icosphere};
+
+/// Cost of moving between neighbouring cells.
+///
+/// `distance` is the great-circle distance between the two cell centres.
+/// Returning `None` makes the step impassable. Costs must not be negative.
+pub trait PathCost {
+    fn cost(&self, from: usize, to: usize, distance: f32) -> Option<f32>;
+}
+impl<F> PathCost for F
+where
+    F: Fn(usize, usize, f32) -> Option<f32>,
+{
+    fn cost(&self, from: usize, to: usize, distance: f32) -> Option<f32> {
+        self(from, to, distance)
+    }
+}
+
+/// Plain surface distance.
+pub struct Distance;
+impl PathCost for Distance {
+    fn cost(&self, _from: usize, _to: usize, distance: f32) -> Option<f32> {
+        Some(distance)
+    }
+}
+
+/// Surface distance weighted by the terrain being crossed.
+pub struct TerrainCost<'a> {
+    /// Elevation of each cell.
+    pub elevation: &'a [f32],
+    pub sea_level: f32,
+    /// Cost multiplier for steps into cells below sea level, or `None` to
+    /// make water impassable.
+    pub water_factor: Option<f32>,
+    /// Extra cost per unit of elevation gained or lost per unit of distance.
+    pub slope_factor: f32,
+}
+impl<'a> TerrainCost<'a> {
+    /// Land-only travel penalising steep slopes.
+    pub fn avoid_water(elevation: &'a [f32], sea_level: f32) -> Self {
+        TerrainCost {
+            elevation,
+            sea_level,
+            water_factor: None,
+            slope_factor: 1.0,
+        }
+    }
+}
+impl PathCost for TerrainCost<'_> {
+    fn cost(&self, from: usize, to: usize, distance: f32) -> Option<f32> {
+        let slope = (self.elevation[to] - self.elevation[from]).abs() / distance;
+        let cost = distance * (1.0 + self.slope_factor * slope);
+        if self.elevation[to] < self.sea_level {
+            self.water_factor.map(|f| cost * f)
+        } else {
+            Some(cost)
+        }
+    }
+}
+
+/// Sequence of neighbouring cells between two cells.
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub cells: Vec<usize>,
+    pub cost: f32,
+}
+
+/// Cheapest path over the cell graph from `from` to `to`, or `None` if `to`
+/// cannot be reached.
+pub fn shortest_path(
+    sphere: &Icosphere,
+    from: usize,
+    to: usize,
+    cost: &impl PathCost,
+) -> Option<Path> {
+    let search = Search::run(sphere, &[from], Some(to), cost);
+    if search.cost[to].is_infinite() {
+        return None;
+    }
+
+    // follow predecessors back to the start
+    let mut cells = vec![to];
+    let mut cell = to;
+    while cell != from {
+        cell = search.previous[cell];
+        cells.push(cell);
+    }
+    cells.reverse();
+
+    Some(Path {
+        cells,
+        cost: search.cost[to],
+    })
+}
+
+/// Cheapest cost from the nearest of `sources` to every cell. Unreachable
+/// cells are `f32::INFINITY`.
+pub fn cost_from(sphere: &Icosphere, sources: &[usize], cost: &impl PathCost) -> Vec<f32> {
+    Search::run(sphere, sources, None, cost).cost
+}
+
+/// Great-circle distance between the centres of two cells.
+pub fn cell_distance(sphere: &Icosphere, a: usize, b: usize) -> f32 {
+    coords::great_circle_distance(
+        sphere.get_position(a),
+        sphere.get_position(b),
+        sphere.get_radius(),
+    )
+}
+
+struct Search {
+    cost: Vec<f32>,
+    previous: Vec<usize>,
+}
+impl Search {
+    // dijkstra's algorithm, stopping early once `target` is settled
+    fn run(
+        sphere: &Icosphere,
+        sources: &[usize],
+        target: Option<usize>,
+        step: &impl PathCost,
+    ) -> Self {
+        let mut cost = vec![f32::INFINITY; sphere.num_cells()];
+        let mut previous: Vec<usize> = (0..sphere.num_cells()).collect();
+        let mut queue = BinaryHeap::new();
+        for &source in sources {
+            cost[source] = 0.0;
+            queue.push(Visit {
+                cell: source,
+                cost: 0.0,
+            });
+        }
+
+        while let Some(Visit { cell, cost: c }) = queue.pop() {
+            if Some(cell) == target {
+                break;
+            }
+            // skip stale queue entries
+            if c > cost[cell] {
+                continue;
+            }
+            for &next in sphere.get_neighbors(cell) {
+                let distance = cell_distance(sphere, cell, next);
+                if let Some(s) = step.cost(cell, next, distance) {
+                    let next_cost = c + s;
+                    if next_cost < cost[next] {
+                        cost[next] = next_cost;
+                        previous[next] = cell;
+                        queue.push(Visit {
+                            cell: next,
+                            cost: next_cost,
+                        });
+                    }
+                }
+            }
+        }
+
+        Search { cost, previous }
+    }
+}
+
+// min-heap entry ordered by cost
+struct Visit {
+    cell: usize,
+    cost: f32,
+}
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Visit {}
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn neighbours_are_one_step_apart() {
+        let sphere = Icosphere::subdivided(1.0, 2);
+        let next = sphere.get_neighbors(5)[0];
+        let path = shortest_path(&sphere, 5, next, &Distance).unwrap();
+        assert_eq!(path.cells, [5, next]);
+        assert_eq!(path.cost, cell_distance(&sphere, 5, next));
+    }
+
+    #[test]
+    fn paths_cost_what_the_cost_field_says() {
+        let sphere = Icosphere::subdivided(1.0, 2);
+        let costs = cost_from(&sphere, &[0], &Distance);
+        for to in (0..sphere.num_cells()).step_by(17) {
+            let path = shortest_path(&sphere, 0, to, &Distance).unwrap();
+            assert_eq!(path.cells.first(), Some(&0));
+            assert_eq!(path.cells.last(), Some(&to));
+            let steps = path
+                .cells
+                .windows(2)
+                .map(|pair| cell_distance(&sphere, pair[0], pair[1]))
+                .sum::<f32>();
+            assert!((path.cost - costs[to]).abs() < 1e-5);
+            assert!((path.cost - steps).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn land_paths_stay_out_of_the_water() {
+        let sphere = Icosphere::subdivided(1.0, 3);
+        // a channel along the equator of y, open only around -x
+        let elevation: Vec<f32> = (0..sphere.num_cells())
+            .map(|cell| {
+                let p = sphere.get_position(cell);
+                if p.y.abs() < 0.15 && p.x > -0.5 {
+                    -1.0
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+        let nearest = |target: Vec3| {
+            (0..sphere.num_cells())
+                .min_by(|&a, &b| {
+                    let d = |cell: usize| sphere.get_position(cell).distance(target);
+                    d(a).total_cmp(&d(b))
+                })
+                .unwrap()
+        };
+        let (from, to) = (
+            nearest(Vec3::new(1.0, 0.5, 0.0)),
+            nearest(Vec3::new(1.0, -0.5, 0.0)),
+        );
+
+        let direct = shortest_path(&sphere, from, to, &Distance).unwrap();
+        assert!(direct.cells.iter().any(|&cell| elevation[cell] < 0.0));
+        let cost = TerrainCost::avoid_water(&elevation, 0.0);
+        let path = shortest_path(&sphere, from, to, &cost).unwrap();
+        assert!(path.cells.iter().all(|&cell| elevation[cell] >= 0.0));
+        assert!(path.cost > direct.cost * 2.0);
+    }
+
+    #[test]
+    fn unreachable_cells_have_no_path() {
+        let sphere = Icosphere::subdivided(1.0, 1);
+        let walled = |_: usize, to: usize, distance: f32| (to != 7).then_some(distance);
+        assert!(shortest_path(&sphere, 0, 7, &walled).is_none());
+        assert!(cost_from(&sphere, &[0], &walled)[7].is_infinite());
+    }
+
+    #[test]
+    fn paths_to_the_start_are_one_cell() {
+        let sphere = Icosphere::subdivided(1.0, 1);
+        let path = shortest_path(&sphere, 3, 3, &Distance).unwrap();
+        assert_eq!(path.cells, [3]);
+        assert_eq!(path.cost, 0.0);
+    }
+}
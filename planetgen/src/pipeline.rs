@@ -0,0 +1,689 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, ensure};
+use instant::Instant;
+
+use crate::{
+    planet::{CellField, PlanetData, Plate},
+    Result,
+};
+
+/// One step of planet generation, reading and writing named per-cell fields
+/// of a [`PlanetData`].
+pub trait Stage {
+    /// Name used to refer to the stage in a [`Pipeline`], unique within it.
+    fn name(&self) -> &str;
+    /// Fields the stage reads, which must exist when it runs.
+    fn inputs(&self) -> &[&str] {
+        &[]
+    }
+    /// Fields the stage adds or replaces.
+    fn outputs(&self) -> &[&str] {
+        &[]
+    }
+    /// Whether the stage makes a new planet, mesh included, rather than
+    /// only changing its outputs, the plates, the time and the timeline.
+    /// Rerunning stages from before such a stage puts the whole planet back
+    /// as it was.
+    fn rebuilds_planet(&self) -> bool {
+        false
+    }
+    /// Rough running time relative to other stages, for estimating
+    /// progress.
+    fn cost(&self) -> f32 {
+        1.0
+    }
+    /// Fill in the outputs. Randomness should come from
+    /// [`Rng::stream`](crate::rng::Rng::stream) with the planet's seed and
+    /// the stage's name. Stages that loop for long should report their
+    /// progress and check for cancellation through `context` every step.
+    fn run(&mut self, planet: &mut PlanetData, context: &mut StageContext) -> Result<()>;
+}
+
+/// How far a run of a [`Pipeline`] is, passed to its progress callback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// Name of the stage running.
+    pub stage: String,
+    /// Share of the whole run done, from 0 to 1.
+    pub fraction: f32,
+    /// Estimated time left, once anything is done.
+    pub eta: Option<Duration>,
+}
+
+/// Flag for stopping a run from elsewhere, such as another thread or a UI
+/// button. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Stop the runs using this token at the next check.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    /// Allow runs again after a cancellation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error a run stops with once its [`CancelToken`] is cancelled, which can
+/// be told apart with `error.is::<Cancelled>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Generation was cancelled")
+    }
+}
+impl std::error::Error for Cancelled {}
+
+/// What a running [`Stage`] can report progress and check cancellation
+/// through.
+pub struct StageContext<'a> {
+    stage: &'a str,
+    // cost of the stages before this one, this one's and the whole run's
+    done: f32,
+    cost: f32,
+    total: f32,
+    started: Instant,
+    cancel: &'a CancelToken,
+    on_progress: Option<&'a mut ProgressCallback>,
+}
+impl StageContext<'_> {
+    /// Tell the progress callback the stage is `fraction` of the way through.
+    pub fn report(&mut self, fraction: f32) {
+        let fraction = (self.done + self.cost * fraction.clamp(0.0, 1.0)) / self.total;
+        if let Some(on_progress) = &mut self.on_progress {
+            on_progress(&progress(self.stage, fraction, self.started));
+        }
+    }
+    /// [`Cancelled`] once the run was cancelled, to return with `?`.
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+type ProgressCallback = dyn FnMut(&Progress);
+
+fn progress(stage: &str, fraction: f32, started: Instant) -> Progress {
+    // assume the rest goes as fast as what is done so far
+    let eta = (fraction > 0.0).then(|| {
+        started
+            .elapsed()
+            .mul_f32((1.0 - fraction).max(0.0) / fraction)
+    });
+    Progress {
+        stage: stage.to_owned(),
+        fraction,
+        eta,
+    }
+}
+
+// what a stage changed in the last run, as it was before the stage
+enum Undo {
+    // the replaced fields, and the plates, time and number of timeline
+    // frames, which stages only add to
+    Outputs {
+        fields: Vec<(String, Option<CellField>)>,
+        plates: Vec<Plate>,
+        time: f64,
+        frames: usize,
+    },
+    Planet(Box<PlanetData>),
+}
+impl Undo {
+    fn new(stage: &dyn Stage, planet: &PlanetData) -> Self {
+        if stage.rebuilds_planet() {
+            return Undo::Planet(Box::new(planet.clone()));
+        }
+        Undo::Outputs {
+            fields: stage
+                .outputs()
+                .iter()
+                .map(|&field| (field.to_owned(), planet.get_field(field).cloned()))
+                .collect(),
+            plates: planet.plates.clone(),
+            time: planet.time,
+            frames: planet.timeline.len(),
+        }
+    }
+
+    fn apply(self, planet: &mut PlanetData) {
+        match self {
+            Undo::Outputs {
+                fields,
+                plates,
+                time,
+                frames,
+            } => {
+                for (field, old) in fields {
+                    match old {
+                        Some(old) => planet.insert_field(field, old),
+                        None => {
+                            planet.remove_field(&field);
+                        }
+                    }
+                }
+                planet.plates = plates;
+                planet.time = time;
+                planet.timeline.truncate(frames);
+            }
+            Undo::Planet(old) => *planet = *old,
+        }
+    }
+}
+
+// the stages a run still has to go through
+struct Running {
+    pending: Vec<usize>,
+    done: f32,
+    total: f32,
+    started: Instant,
+}
+
+/// Ordered set of stages run over a shared [`PlanetData`].
+///
+/// Stages run after the stages producing their inputs, whatever order they
+/// were added in. Stages that both read and write a field, such as erosion
+/// changing elevation, run in the order they were added among themselves.
+///
+/// A run either goes through at once with [`Pipeline::run`], or a stage at a
+/// time with [`Pipeline::start`] and [`Pipeline::step`] so a UI can keep
+/// drawing in between.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    skipped: HashSet<String>,
+    // what each stage changed in the last run
+    undo: HashMap<String, Undo>,
+    running: Option<Running>,
+    cancel: CancelToken,
+    on_progress: Option<Box<ProgressCallback>>,
+}
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stage after those already in the pipeline.
+    pub fn push(&mut self, stage: impl Stage + 'static) -> &mut Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+    /// Add a stage just before the stage called `before`.
+    pub fn insert_before(&mut self, before: &str, stage: impl Stage + 'static) -> Result<()> {
+        let i = self.position(before)?;
+        self.stages.insert(i, Box::new(stage));
+        Ok(())
+    }
+    /// Add a stage just after the stage called `after`.
+    pub fn insert_after(&mut self, after: &str, stage: impl Stage + 'static) -> Result<()> {
+        let i = self.position(after)?;
+        self.stages.insert(i + 1, Box::new(stage));
+        Ok(())
+    }
+    /// Swap the stage called `name` for another, for example the same stage
+    /// with different parameters.
+    pub fn replace(&mut self, name: &str, stage: impl Stage + 'static) -> Result<()> {
+        let i = self.position(name)?;
+        self.stages[i] = Box::new(stage);
+        Ok(())
+    }
+    pub fn remove(&mut self, name: &str) -> Result<Box<dyn Stage>> {
+        let i = self.position(name)?;
+        Ok(self.stages.remove(i))
+    }
+    /// Leave the stage called `name` out of runs, or put it back. Fields it
+    /// would produce must then already be in the planet.
+    pub fn skip(&mut self, name: &str, skip: bool) -> Result<()> {
+        self.position(name)?;
+        if skip {
+            self.skipped.insert(name.to_owned());
+        } else {
+            self.skipped.remove(name);
+        }
+        Ok(())
+    }
+
+    /// Call `on_progress` as runs go on, at the start of every stage, when
+    /// stages report and once the run is done.
+    pub fn on_progress(&mut self, on_progress: impl FnMut(&Progress) + 'static) {
+        self.on_progress = Some(Box::new(on_progress));
+    }
+    /// Token that stops runs of this pipeline with [`Cancelled`].
+    pub fn get_cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    /// Names of the stages in the order they run.
+    pub fn get_order(&self) -> Result<Vec<&str>> {
+        Ok(self
+            .order()?
+            .into_iter()
+            .map(|i| self.stages[i].name())
+            .collect())
+    }
+
+    /// Run every stage that isn't skipped.
+    pub fn run(&mut self, planet: &mut PlanetData) -> Result<()> {
+        self.start()?;
+        while self.step(planet)? {}
+        Ok(())
+    }
+
+    /// Run the stage called `name` and every stage after it again on the
+    /// planet from the last run, reusing the fields earlier stages made. Use
+    /// this after changing a stage's parameters with [`Pipeline::replace`].
+    ///
+    /// The fields, plates, time and timeline frames of the stages rerun are
+    /// put back as they were before them first, or the whole planet if one
+    /// of them [rebuilds it](Stage::rebuilds_planet).
+    pub fn run_from(&mut self, planet: &mut PlanetData, name: &str) -> Result<()> {
+        self.start_from(planet, name)?;
+        while self.step(planet)? {}
+        Ok(())
+    }
+
+    /// Begin a run of every stage that isn't skipped, to go through with
+    /// [`Pipeline::step`].
+    pub fn start(&mut self) -> Result<()> {
+        let order = self.order()?;
+        self.undo.clear();
+        self.begin(order);
+        Ok(())
+    }
+
+    /// Begin a run like [`Pipeline::run_from`], to go through with
+    /// [`Pipeline::step`].
+    pub fn start_from(&mut self, planet: &mut PlanetData, name: &str) -> Result<()> {
+        let i = self.position(name)?;
+        let mut order = self.order()?;
+        let start = order.iter().position(|&j| j == i).unwrap();
+        // undo the stages being rerun, latest first, so stages updating a
+        // field in place start from what they saw the first time
+        for &j in order[start..].iter().rev() {
+            if let Some(undo) = self.undo.remove(self.stages[j].name()) {
+                undo.apply(planet);
+            }
+        }
+        self.begin(order.split_off(start));
+        Ok(())
+    }
+
+    /// Run the next stage of the run begun with [`Pipeline::start`], giving
+    /// whether any stages are left. A failed stage ends the run.
+    pub fn step(&mut self, planet: &mut PlanetData) -> Result<bool> {
+        let result = self.step_stage(planet);
+        if !matches!(result, Ok(true)) {
+            self.running = None;
+        }
+        result
+    }
+
+    /// Whether a run was started and hasn't finished.
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    fn begin(&mut self, order: Vec<usize>) {
+        let mut pending: Vec<usize> = order
+            .into_iter()
+            .filter(|&i| !self.skipped.contains(self.stages[i].name()))
+            .collect();
+        let total = pending.iter().map(|&i| self.stages[i].cost()).sum();
+        // popped from the back
+        pending.reverse();
+        self.running = Some(Running {
+            pending,
+            done: 0.0,
+            total,
+            started: Instant::now(),
+        });
+    }
+
+    fn step_stage(&mut self, planet: &mut PlanetData) -> Result<bool> {
+        let running = match &mut self.running {
+            Some(running) => running,
+            None => bail!("The pipeline isn't running, start it first"),
+        };
+        let i = match running.pending.pop() {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+        let stage = &mut self.stages[i];
+        if self.cancel.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        for input in stage.inputs() {
+            ensure!(
+                planet.get_field(input).is_some(),
+                "Stage {} needs the field {}, which nothing has produced",
+                stage.name(),
+                input
+            );
+        }
+        self.undo
+            .insert(stage.name().to_owned(), Undo::new(stage.as_ref(), planet));
+
+        let name = stage.name().to_owned();
+        let mut context = StageContext {
+            stage: &name,
+            done: running.done,
+            cost: stage.cost(),
+            total: running.total.max(f32::MIN_POSITIVE),
+            started: running.started,
+            cancel: &self.cancel,
+            on_progress: match &mut self.on_progress {
+                Some(on_progress) => Some(on_progress.as_mut()),
+                None => None,
+            },
+        };
+        context.report(0.0);
+        stage.run(planet, &mut context)?;
+        for output in stage.outputs() {
+            ensure!(
+                planet.get_field(output).is_some(),
+                "Stage {} didn't produce its field {}",
+                stage.name(),
+                output
+            );
+        }
+
+        running.done += stage.cost();
+        if running.pending.is_empty() {
+            if let Some(on_progress) = &mut self.on_progress {
+                on_progress(&progress(&name, 1.0, running.started));
+            }
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn position(&self, name: &str) -> Result<usize> {
+        match self.stages.iter().position(|s| s.name() == name) {
+            Some(i) => Ok(i),
+            None => bail!("No stage called {} in the pipeline", name),
+        }
+    }
+
+    // indices of the stages in an order where every stage comes after the
+    // stages it depends on, otherwise keeping the order they were added in
+    fn order(&self) -> Result<Vec<usize>> {
+        let n = self.stages.len();
+        let mut names = HashSet::new();
+        for stage in &self.stages {
+            ensure!(
+                names.insert(stage.name()),
+                "Two stages are called {}",
+                stage.name()
+            );
+        }
+
+        let mut depends = vec![Vec::new(); n];
+        for (i, stage) in self.stages.iter().enumerate() {
+            for input in stage.inputs() {
+                for (j, other) in self.stages.iter().enumerate() {
+                    if i == j || !other.outputs().contains(input) {
+                        continue;
+                    }
+                    // a stage updating the field in place only counts if it
+                    // was added earlier, otherwise it comes after us
+                    if !other.inputs().contains(input) || j < i {
+                        depends[i].push(j);
+                    }
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(n);
+        let mut placed = vec![false; n];
+        while order.len() < n {
+            let next = (0..n).find(|&i| !placed[i] && depends[i].iter().all(|&j| placed[j]));
+            match next {
+                Some(i) => {
+                    placed[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    let stuck: Vec<&str> = (0..n)
+                        .filter(|&i| !placed[i])
+                        .map(|i| self.stages[i].name())
+                        .collect();
+                    bail!(
+                        "Stages {} depend on each other in a cycle",
+                        stuck.join(", ")
+                    );
+                }
+            }
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use glam::Vec3;
+
+    use super::*;
+    use crate::{
+        generate::standard_pipeline, planet::GenerationParams, save::write_planet, timeline::Frame,
+    };
+
+    // adds a plate, a frame and a unit of time every run
+    struct Tick(&'static str);
+    impl Stage for Tick {
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+            planet.time += 1.0;
+            planet.plates.push(Plate {
+                axis: Vec3::Z,
+                angular_speed: 0.0,
+            });
+            let cells = planet.sphere.num_cells();
+            planet.timeline.push(Frame {
+                time: planet.time,
+                plate: vec![0; cells].into(),
+                crust: vec![0.0; cells].into(),
+            });
+            Ok(())
+        }
+    }
+
+    // records running and fills its outputs with zeros
+    struct Fields {
+        name: &'static str,
+        inputs: &'static [&'static str],
+        outputs: &'static [&'static str],
+        ran: Rc<RefCell<Vec<&'static str>>>,
+    }
+    impl Stage for Fields {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn inputs(&self) -> &[&str] {
+            self.inputs
+        }
+        fn outputs(&self) -> &[&str] {
+            self.outputs
+        }
+        fn run(&mut self, planet: &mut PlanetData, _: &mut StageContext) -> Result<()> {
+            self.ran.borrow_mut().push(self.name);
+            let cells = planet.sphere.num_cells();
+            for &output in self.outputs {
+                planet.insert_field(output, CellField::Scalar(vec![0.0; cells].into()));
+            }
+            Ok(())
+        }
+    }
+
+    fn fields(
+        name: &'static str,
+        inputs: &'static [&'static str],
+        outputs: &'static [&'static str],
+        ran: &Rc<RefCell<Vec<&'static str>>>,
+    ) -> Fields {
+        Fields {
+            name,
+            inputs,
+            outputs,
+            ran: ran.clone(),
+        }
+    }
+
+    fn planet() -> PlanetData {
+        PlanetData::new(GenerationParams {
+            subdivisions: 0,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn reruns_put_back_plates_time_and_frames() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Tick("a")).push(Tick("b"));
+        let mut planet = planet();
+        pipeline.run(&mut planet).unwrap();
+        pipeline.run_from(&mut planet, "b").unwrap();
+        assert_eq!(planet.time, 2.0);
+        assert_eq!(planet.plates.len(), 2);
+        assert_eq!(planet.timeline.len(), 2);
+        pipeline.run_from(&mut planet, "a").unwrap();
+        assert_eq!(planet.time, 2.0);
+        assert_eq!(planet.plates.len(), 2);
+        assert_eq!(planet.timeline.len(), 2);
+    }
+
+    #[test]
+    fn cancelling_between_stages_stops_the_run() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Tick("a")).push(Tick("b"));
+        let mut planet = planet();
+        pipeline.start().unwrap();
+        assert!(pipeline.step(&mut planet).unwrap());
+        pipeline.get_cancel_token().cancel();
+        let error = pipeline.step(&mut planet).unwrap_err();
+        assert!(error.is::<Cancelled>());
+        assert!(!pipeline.is_running());
+        assert_eq!(planet.time, 1.0);
+    }
+
+    #[test]
+    fn progress_only_goes_forward() {
+        let fractions = Rc::new(RefCell::new(Vec::new()));
+        let reported = fractions.clone();
+        let mut pipeline = standard_pipeline();
+        pipeline.on_progress(move |progress| reported.borrow_mut().push(progress.fraction));
+        let mut planet = PlanetData::new(GenerationParams {
+            subdivisions: 2,
+            ..Default::default()
+        });
+        pipeline.run(&mut planet).unwrap();
+        let fractions = fractions.borrow();
+        assert!(fractions.len() > pipeline.get_order().unwrap().len());
+        assert!(fractions.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn stepping_matches_running() {
+        let params = GenerationParams {
+            seed: 3,
+            subdivisions: 2,
+            ..Default::default()
+        };
+        let mut run = PlanetData::new(params);
+        standard_pipeline().run(&mut run).unwrap();
+        let mut stepped = PlanetData::new(params);
+        let mut pipeline = standard_pipeline();
+        pipeline.start().unwrap();
+        while pipeline.step(&mut stepped).unwrap() {}
+
+        let bytes = |planet: &PlanetData| {
+            let mut bytes = Vec::new();
+            write_planet(planet, &mut bytes).unwrap();
+            bytes
+        };
+        assert_eq!(bytes(&stepped), bytes(&run));
+    }
+
+    #[test]
+    fn stages_run_after_what_they_read() {
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .push(fields("climate", &["elevation"], &["temperature"], &ran))
+            .push(fields("terrain", &[], &["elevation"], &ran));
+        assert_eq!(pipeline.get_order().unwrap(), ["terrain", "climate"]);
+        pipeline.run(&mut planet()).unwrap();
+        assert_eq!(*ran.borrow(), ["terrain", "climate"]);
+    }
+
+    #[test]
+    fn dependency_cycles_are_errors() {
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .push(fields("a", &["y"], &["x"], &ran))
+            .push(fields("b", &["x"], &["y"], &ran));
+        let error = pipeline.run(&mut planet()).unwrap_err();
+        assert!(error.to_string().contains("cycle"), "{}", error);
+        assert!(ran.borrow().is_empty());
+    }
+
+    #[test]
+    fn skipped_stages_leave_their_readers_without_input() {
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .push(fields("terrain", &[], &["elevation"], &ran))
+            .push(fields("climate", &["elevation"], &["temperature"], &ran));
+        pipeline.skip("terrain", true).unwrap();
+        let error = pipeline.run(&mut planet()).unwrap_err();
+        assert!(
+            error.to_string().contains("needs the field elevation"),
+            "{}",
+            error
+        );
+        assert!(ran.borrow().is_empty());
+
+        pipeline.skip("terrain", false).unwrap();
+        pipeline.run(&mut planet()).unwrap();
+        assert_eq!(*ran.borrow(), ["terrain", "climate"]);
+    }
+
+    #[test]
+    fn unknown_stage_names_are_errors() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Tick("a"));
+        assert!(pipeline.insert_before("b", Tick("c")).is_err());
+        assert!(pipeline.insert_after("b", Tick("c")).is_err());
+        assert!(pipeline.skip("b", true).is_err());
+        pipeline.insert_before("a", Tick("b")).unwrap();
+        pipeline.insert_after("a", Tick("c")).unwrap();
+        assert_eq!(pipeline.get_order().unwrap(), ["b", "a", "c"]);
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use glam::Vec3;
+
+use crate::{
+    field::{Field, FieldValue},
+    icosphere::Icosphere,
+    timeline::Timeline,
+};
+
+/// Settings a planet is generated from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GenerationParams {
+    pub seed: u64,
+    /// In any unit, metres giving a planet at real scale.
+    pub radius: f64,
+    /// Times the icosahedron is subdivided to make the cell mesh.
+    pub subdivisions: u32,
+    /// Number of tectonic plates.
+    pub plates: u32,
+}
+impl Default for GenerationParams {
+    fn default() -> Self {
+        GenerationParams {
+            seed: 0,
+            radius: 1.0,
+            subdivisions: 5,
+            plates: 12,
+        }
+    }
+}
+
+/// Rigid tectonic plate turning about an axis through the planet centre.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plate {
+    /// Unit axis of rotation, the plate's Euler pole.
+    pub axis: Vec3,
+    /// Radians per unit of simulation time, counter-clockwise about `axis`.
+    pub angular_speed: f32,
+}
+impl Plate {
+    /// Surface velocity of the plate at `pos`.
+    pub fn velocity(&self, pos: Vec3) -> Vec3 {
+        (self.axis * self.angular_speed).cross(pos)
+    }
+}
+
+/// Values stored for every cell of the planet.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "kind", content = "values", rename_all = "lowercase")
+)]
+pub enum CellField {
+    Scalar(Field<f32>),
+    /// Categorical values such as plate ids.
+    Id(Field<u32>),
+    Vector(Field<Vec3>),
+}
+impl CellField {
+    pub fn len(&self) -> usize {
+        match self {
+            CellField::Scalar(values) => values.len(),
+            CellField::Id(values) => values.len(),
+            CellField::Vector(values) => values.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Complete state of a planet: the cell mesh, named per-cell fields, the
+/// plates and how far its simulation has run.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlanetData {
+    /// Simulation time reached.
+    pub time: f64,
+    pub params: GenerationParams,
+    pub sphere: Icosphere,
+    pub plates: Vec<Plate>,
+    /// Plates and elevation through the drift, when it was recorded.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timeline: Timeline,
+    fields: BTreeMap<String, CellField>,
+}
+impl PlanetData {
+    /// Planet with the mesh described by `params` and nothing else yet.
+    pub fn new(params: GenerationParams) -> Self {
+        let sphere = Icosphere::subdivided_f64(params.radius, params.subdivisions);
+        PlanetData::from_sphere(params, sphere)
+    }
+    pub fn from_sphere(params: GenerationParams, sphere: Icosphere) -> Self {
+        PlanetData {
+            time: 0.0,
+            params,
+            sphere,
+            plates: Vec::new(),
+            timeline: Timeline::new(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Split every face of the mesh into four, extending the fields over the
+    /// new cells.
+    pub fn subdivide(&mut self) {
+        let parents = self.sphere.subdivide();
+        for field in self.fields.values_mut() {
+            match field {
+                CellField::Scalar(field) => field.subdivide(&parents),
+                CellField::Id(field) => field.subdivide(&parents),
+                CellField::Vector(field) => field.subdivide(&parents),
+            }
+        }
+        self.timeline.subdivide(&parents);
+        self.params.subdivisions += 1;
+    }
+
+    /// Add or replace a field, which must have one value per cell.
+    pub fn insert<T: FieldValue>(&mut self, name: impl Into<String>, field: Field<T>) {
+        self.insert_field(name, T::into_cell_field(field));
+    }
+    /// Field called `name`, if there is one holding values of type `T`.
+    pub fn get<T: FieldValue>(&self, name: &str) -> Option<&Field<T>> {
+        self.fields.get(name).and_then(T::from_cell_field)
+    }
+    pub fn get_mut<T: FieldValue>(&mut self, name: &str) -> Option<&mut Field<T>> {
+        self.fields.get_mut(name).and_then(T::from_cell_field_mut)
+    }
+
+    /// Add or replace a field, which must have one value per cell.
+    pub fn insert_field(&mut self, name: impl Into<String>, field: CellField) {
+        assert_eq!(field.len(), self.sphere.num_cells());
+        self.fields.insert(name.into(), field);
+    }
+    pub fn remove_field(&mut self, name: &str) -> Option<CellField> {
+        self.fields.remove(name)
+    }
+    pub fn get_field(&self, name: &str) -> Option<&CellField> {
+        self.fields.get(name)
+    }
+    /// Fields in order of name.
+    pub fn get_fields(&self) -> impl Iterator<Item = (&str, &CellField)> {
+        self.fields
+            .iter()
+            .map(|(name, field)| (name.as_str(), field))
+    }
+
+    pub fn get_scalar(&self, name: &str) -> Option<&[f32]> {
+        self.get::<f32>(name).map(Field::get_values)
+    }
+    pub fn get_ids(&self, name: &str) -> Option<&[u32]> {
+        self.get::<u32>(name).map(Field::get_values)
+    }
+    pub fn get_vectors(&self, name: &str) -> Option<&[Vec3]> {
+        self.get::<Vec3>(name).map(Field::get_values)
+    }
+}
@@ -0,0 +1,567 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI, SQRT_2};
+
+use glam::{Vec2, Vec3};
+
+use crate::{coords::LatLon, icosphere::Icosphere};
+
+/// Map projection between positions on a unit sphere and a flat plane.
+///
+/// Plane coordinates have +x to the east and +y to the north.
+pub trait Projection {
+    /// Plane coordinates of `lat_lon`, or `None` if the projection doesn't
+    /// show that part of the sphere.
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2>;
+    /// Position shown at `point`, or `None` if `point` is off the map.
+    fn inverse(&self, point: Vec2) -> Option<LatLon>;
+    /// Lower left and upper right corners of the projected map.
+    fn bounds(&self) -> (Vec2, Vec2);
+
+    /// Image height that keeps the map's aspect ratio at the given width.
+    fn height_for_width(&self, width: u32) -> u32 {
+        let (min, max) = self.bounds();
+        let size = max - min;
+        ((width as f32 * size.y / size.x).round() as u32).max(1)
+    }
+}
+
+/// Plate carrée: longitude and latitude used directly as x and y.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Equirectangular {
+    pub central_meridian: f32,
+}
+impl Projection for Equirectangular {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let lon = LatLon::new(0.0, lat_lon.lon - self.central_meridian)
+            .normalized()
+            .lon;
+        Some(Vec2::new(lon, lat_lon.lat))
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        if point.x.abs() > PI || point.y.abs() > FRAC_PI_2 {
+            return None;
+        }
+        Some(LatLon::new(point.y, point.x + self.central_meridian).normalized())
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        (Vec2::new(-PI, -FRAC_PI_2), Vec2::new(PI, FRAC_PI_2))
+    }
+}
+
+/// Equal-area pseudocylindrical projection with an elliptical outline.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mollweide {
+    pub central_meridian: f32,
+}
+impl Projection for Mollweide {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let lon = LatLon::new(0.0, lat_lon.lon - self.central_meridian)
+            .normalized()
+            .lon;
+        Some(mollweide_forward(lat_lon.lat, lon))
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        let (lat, lon) = mollweide_inverse(point)?;
+        Some(LatLon::new(lat, lon + self.central_meridian).normalized())
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let max = Vec2::new(2.0 * SQRT_2, SQRT_2);
+        (-max, max)
+    }
+}
+
+fn mollweide_forward(lat: f32, lon: f32) -> Vec2 {
+    // solve 2t + sin(2t) = pi * sin(lat) with newton's method
+    let target = PI * lat.sin();
+    let mut theta = lat;
+    for _ in 0..20 {
+        let f = 2.0 * theta + (2.0 * theta).sin() - target;
+        let df = 2.0 + 2.0 * (2.0 * theta).cos();
+        if df.abs() < 1e-6 {
+            break;
+        }
+        let step = f / df;
+        theta -= step;
+        if step.abs() < 1e-7 {
+            break;
+        }
+    }
+    Vec2::new(2.0 * SQRT_2 / PI * lon * theta.cos(), SQRT_2 * theta.sin())
+}
+
+fn mollweide_inverse(point: Vec2) -> Option<(f32, f32)> {
+    let s = point.y / SQRT_2;
+    if s.abs() > 1.0 {
+        return None;
+    }
+    let theta = s.asin();
+    let lat = ((2.0 * theta + (2.0 * theta).sin()) / PI)
+        .clamp(-1.0, 1.0)
+        .asin();
+    let lon = if theta.cos() > 1e-6 {
+        PI * point.x / (2.0 * SQRT_2 * theta.cos())
+    } else {
+        0.0
+    };
+    if lon.abs() > PI {
+        return None;
+    }
+    Some((lat, lon))
+}
+
+/// Compromise pseudocylindrical projection defined by Robinson's table of
+/// parallel lengths and distances from the equator.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Robinson {
+    pub central_meridian: f32,
+}
+
+// table values at every 5 degrees of latitude from the equator to the pole
+const ROBINSON_X: [f32; 19] = [
+    1.0000, 0.9986, 0.9954, 0.9900, 0.9822, 0.9730, 0.9600, 0.9427, 0.9216, 0.8962, 0.8679, 0.8350,
+    0.7986, 0.7597, 0.7186, 0.6732, 0.6213, 0.5722, 0.5322,
+];
+const ROBINSON_Y: [f32; 19] = [
+    0.0000, 0.0620, 0.1240, 0.1860, 0.2480, 0.3100, 0.3720, 0.4340, 0.4958, 0.5571, 0.6176, 0.6769,
+    0.7346, 0.7903, 0.8435, 0.8936, 0.9394, 0.9761, 1.0000,
+];
+const ROBINSON_X_SCALE: f32 = 0.8487;
+const ROBINSON_Y_SCALE: f32 = 1.3523;
+
+impl Projection for Robinson {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let lon = LatLon::new(0.0, lat_lon.lon - self.central_meridian)
+            .normalized()
+            .lon;
+        // interpolate between the rows of the table
+        let t = (lat_lon.lat.abs().to_degrees() / 5.0).min(18.0);
+        let i = (t as usize).min(17);
+        let f = t - i as f32;
+        let x = ROBINSON_X[i] + (ROBINSON_X[i + 1] - ROBINSON_X[i]) * f;
+        let y = ROBINSON_Y[i] + (ROBINSON_Y[i + 1] - ROBINSON_Y[i]) * f;
+        Some(Vec2::new(
+            ROBINSON_X_SCALE * x * lon,
+            ROBINSON_Y_SCALE * y * lat_lon.lat.signum(),
+        ))
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        let y = point.y.abs() / ROBINSON_Y_SCALE;
+        if y > 1.0 {
+            return None;
+        }
+        // find the table row bracketing y, which increases monotonically
+        let i = ROBINSON_Y.partition_point(|&v| v <= y).clamp(1, 18) - 1;
+        let f = (y - ROBINSON_Y[i]) / (ROBINSON_Y[i + 1] - ROBINSON_Y[i]);
+        let lat = (5.0 * (i as f32 + f)).to_radians() * point.y.signum();
+        let x = ROBINSON_X[i] + (ROBINSON_X[i + 1] - ROBINSON_X[i]) * f;
+        let lon = point.x / (ROBINSON_X_SCALE * x);
+        if lon.abs() > PI {
+            return None;
+        }
+        Some(LatLon::new(lat, lon + self.central_meridian).normalized())
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let max = Vec2::new(ROBINSON_X_SCALE * PI, ROBINSON_Y_SCALE);
+        (-max, max)
+    }
+}
+
+/// View of one hemisphere as seen from infinitely far away.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Orthographic {
+    pub center: LatLon,
+}
+impl Projection for Orthographic {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let (east, north, view) = self.frame();
+        let dir = lat_lon.to_unit_vec3();
+        // hide the far side of the sphere
+        if dir.dot(view) < 0.0 {
+            return None;
+        }
+        Some(Vec2::new(dir.dot(east), dir.dot(north)))
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        let depth = 1.0 - point.length_squared();
+        if depth < 0.0 {
+            return None;
+        }
+        let (east, north, view) = self.frame();
+        Some(LatLon::from_vec3(
+            east * point.x + north * point.y + view * depth.sqrt(),
+        ))
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        (Vec2::NEG_ONE, Vec2::ONE)
+    }
+}
+impl Orthographic {
+    // local east, north and outward directions at the centre of the view
+    fn frame(&self) -> (Vec3, Vec3, Vec3) {
+        let view = self.center.to_unit_vec3();
+        let (sin_lon, cos_lon) = self.center.lon.sin_cos();
+        let east = Vec3::new(-sin_lon, cos_lon, 0.0);
+        (east, view.cross(east), view)
+    }
+}
+
+/// Conformal azimuthal projection centred on a pole.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolarStereographic {
+    /// Centre on the north pole if true, otherwise the south pole.
+    pub north: bool,
+    /// Latitude of the outer edge of the map, measured from the equator
+    /// towards the centre pole.
+    pub boundary_lat: f32,
+}
+impl Default for PolarStereographic {
+    fn default() -> Self {
+        PolarStereographic {
+            north: true,
+            boundary_lat: 0.0,
+        }
+    }
+}
+impl PolarStereographic {
+    // distance from the pole on the map, for latitude measured towards it
+    fn radius(lat: f32) -> f32 {
+        2.0 * (FRAC_PI_4 - lat * 0.5).tan()
+    }
+    fn max_radius(&self) -> f32 {
+        PolarStereographic::radius(self.boundary_lat)
+    }
+}
+impl Projection for PolarStereographic {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let sign = if self.north { 1.0 } else { -1.0 };
+        let lat = lat_lon.lat * sign;
+        if lat < self.boundary_lat {
+            return None;
+        }
+        let r = PolarStereographic::radius(lat);
+        let (sin_lon, cos_lon) = lat_lon.lon.sin_cos();
+        // the prime meridian points down from the north pole and up from the south
+        Some(Vec2::new(r * sin_lon, -r * cos_lon * sign))
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        let sign = if self.north { 1.0 } else { -1.0 };
+        let r = point.length();
+        if r > self.max_radius() {
+            return None;
+        }
+        let lat = FRAC_PI_2 - 2.0 * (r * 0.5).atan();
+        let lon = point.x.atan2(-point.y * sign);
+        Some(LatLon::new(lat * sign, lon))
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let max = Vec2::splat(self.max_radius());
+        (-max, max)
+    }
+}
+
+/// Goode's interrupted homolosine projection, an equal-area projection
+/// joining sinusoidal and Mollweide projections in several lobes.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GoodeHomolosine;
+
+// latitude where the sinusoidal and mollweide parallels have equal length
+const HOMOLOSINE_LAT: f32 = 0.710_988;
+// vertical offset making the mollweide part meet the sinusoidal part
+const HOMOLOSINE_OFFSET: f32 = 0.052_803_5;
+// height of the poles above the equator
+const HOMOLOSINE_MAX_Y: f32 = SQRT_2 - HOMOLOSINE_OFFSET;
+// (central meridian, west edge, east edge) of each lobe in degrees
+const GOODE_NORTH_LOBES: [(f32, f32, f32); 2] = [(-100.0, -180.0, -40.0), (30.0, -40.0, 180.0)];
+const GOODE_SOUTH_LOBES: [(f32, f32, f32); 4] = [
+    (-160.0, -180.0, -100.0),
+    (-60.0, -100.0, -20.0),
+    (20.0, -20.0, 80.0),
+    (140.0, 80.0, 180.0),
+];
+
+impl GoodeHomolosine {
+    fn lobes(north: bool) -> &'static [(f32, f32, f32)] {
+        if north {
+            &GOODE_NORTH_LOBES
+        } else {
+            &GOODE_SOUTH_LOBES
+        }
+    }
+}
+impl Projection for GoodeHomolosine {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let LatLon { lat, lon } = lat_lon.normalized();
+        let lon_deg = lon.to_degrees();
+        let &(center, _, _) = GoodeHomolosine::lobes(lat >= 0.0)
+            .iter()
+            .find(|&&(_, west, east)| lon_deg >= west && lon_deg <= east)?;
+        let center = center.to_radians();
+        let lon = lon - center;
+
+        let point = if lat.abs() <= HOMOLOSINE_LAT {
+            Vec2::new(lon * lat.cos(), lat)
+        } else {
+            let p = mollweide_forward(lat, lon);
+            Vec2::new(p.x, p.y - HOMOLOSINE_OFFSET * lat.signum())
+        };
+        Some(point + Vec2::new(center, 0.0))
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        if point.y.abs() > HOMOLOSINE_MAX_Y {
+            return None;
+        }
+        let sinusoidal = point.y.abs() <= HOMOLOSINE_LAT;
+        GoodeHomolosine::lobes(point.y >= 0.0)
+            .iter()
+            .find_map(|&(center, west, east)| {
+                let center_rad = center.to_radians();
+                let local = Vec2::new(point.x - center_rad, point.y);
+                let (lat, lon) = if sinusoidal {
+                    let cos_lat = local.y.cos();
+                    if cos_lat < 1e-6 {
+                        return None;
+                    }
+                    (local.y, local.x / cos_lat)
+                } else {
+                    let y = local.y + HOMOLOSINE_OFFSET * local.y.signum();
+                    mollweide_inverse(Vec2::new(local.x, y))?
+                };
+                let lon_deg = (lon + center_rad).to_degrees();
+                (lon_deg >= west && lon_deg <= east).then(|| LatLon::new(lat, lon + center_rad))
+            })
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        (
+            Vec2::new(-PI, -HOMOLOSINE_MAX_Y),
+            Vec2::new(PI, HOMOLOSINE_MAX_Y),
+        )
+    }
+}
+
+/// Icosahedral net in the style of Fuller's dymaxion map.
+///
+/// Each point is projected gnomonically onto its face of the icosahedron,
+/// and the 20 faces are unfolded into a strip of five upper, ten middle and
+/// five lower triangles with unit edge length.
+#[derive(Debug, Clone)]
+pub struct IcosahedralNet {
+    // unit direction of each icosahedron vertex
+    vertices: Vec<Vec3>,
+    // corners of each face in 3d and on the net
+    faces: Vec<([usize; 3], [Vec2; 3])>,
+}
+impl Default for IcosahedralNet {
+    fn default() -> Self {
+        IcosahedralNet::new()
+    }
+}
+impl IcosahedralNet {
+    pub fn new() -> Self {
+        let icosahedron = Icosphere::new(1.0);
+        let vertices: Vec<Vec3> = (0..icosahedron.num_cells())
+            .map(|i| icosahedron.get_position(i))
+            .collect();
+
+        let h = 3.0f32.sqrt() * 0.5;
+        let faces = icosahedron
+            .get_faces()
+            .iter()
+            .map(|face| {
+                let corners = face.get_corners();
+                // columns of the ring vertices in the strip, wrapping the
+                // first column around to the end when a face spans the seam
+                let column = |v: usize| match v {
+                    1..=5 => Some(v - 1),
+                    6..=10 => Some(v - 6),
+                    _ => None,
+                };
+                let wrap = corners.iter().filter_map(|&v| column(v)).any(|c| c == 4);
+                let mut net = corners.map(|v| match column(v) {
+                    Some(c) => {
+                        let c = if wrap && c == 0 { 5 } else { c } as f32;
+                        if v <= 5 {
+                            Vec2::new(c + 0.5, h)
+                        } else {
+                            Vec2::new(c, 0.0)
+                        }
+                    }
+                    None => Vec2::ZERO,
+                });
+                // place the poles over the middle of their opposite edge
+                for i in 0..3 {
+                    if column(corners[i]).is_none() {
+                        let x = (net[(i + 1) % 3].x + net[(i + 2) % 3].x) * 0.5;
+                        let y = if corners[i] == 0 { 2.0 * h } else { -h };
+                        net[i] = Vec2::new(x, y);
+                    }
+                }
+                (corners, net)
+            })
+            .collect();
+
+        IcosahedralNet { vertices, faces }
+    }
+}
+impl Projection for IcosahedralNet {
+    fn forward(&self, lat_lon: LatLon) -> Option<Vec2> {
+        let dir = lat_lon.to_unit_vec3();
+        // the face whose centre is nearest contains the point
+        let (corners, net) = self.faces.iter().max_by(|a, b| {
+            let centre = |f: &([usize; 3], [Vec2; 3])| {
+                let [a, b, c] = f.0.map(|v| self.vertices[v]);
+                (a + b + c).dot(dir)
+            };
+            centre(a).total_cmp(&centre(b))
+        })?;
+        let [a, b, c] = corners.map(|v| self.vertices[v]);
+        let normal = (b - a).cross(c - a);
+        let p = dir * (normal.dot(a) / normal.dot(dir));
+        let area = normal.length_squared();
+        let weights = [
+            (b - p).cross(c - p).dot(normal) / area,
+            (c - p).cross(a - p).dot(normal) / area,
+            (a - p).cross(b - p).dot(normal) / area,
+        ];
+        Some(net[0] * weights[0] + net[1] * weights[1] + net[2] * weights[2])
+    }
+    fn inverse(&self, point: Vec2) -> Option<LatLon> {
+        self.faces.iter().find_map(|(corners, [a, b, c])| {
+            let cross = |u: Vec2, v: Vec2| u.x * v.y - u.y * v.x;
+            let area = cross(*b - *a, *c - *a);
+            let weights = [
+                cross(*b - point, *c - point) / area,
+                cross(*c - point, *a - point) / area,
+                cross(*a - point, *b - point) / area,
+            ];
+            if weights.iter().any(|&w| w < -1e-6) {
+                return None;
+            }
+            let p = (0..3).fold(Vec3::ZERO, |sum, i| {
+                sum + self.vertices[corners[i]] * weights[i]
+            });
+            Some(LatLon::from_vec3(p))
+        })
+    }
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let h = 3.0f32.sqrt() * 0.5;
+        (Vec2::new(0.0, -h), Vec2::new(5.5, 2.0 * h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every 7.5 degrees, skipping the antimeridian where longitude is
+    // ambiguous
+    fn grid() -> impl Iterator<Item = LatLon> {
+        (-12..=12).flat_map(|i| {
+            (-23..=23).map(move |j| LatLon::from_degrees(i as f32 * 7.5, j as f32 * 7.5))
+        })
+    }
+
+    // forward then inverse lands back on the same point, inside the bounds,
+    // for every point the projection shows; returns how many it showed
+    fn assert_round_trip(projection: &dyn Projection, tolerance: f32) -> usize {
+        let (min, max) = projection.bounds();
+        let mut shown = 0;
+        for lat_lon in grid() {
+            let point = match projection.forward(lat_lon) {
+                Some(point) => point,
+                None => continue,
+            };
+            shown += 1;
+            assert!(
+                point.cmpge(min - 1e-4).all() && point.cmple(max + 1e-4).all(),
+                "{:?} maps to {:?} outside {:?}",
+                lat_lon.to_degrees(),
+                point,
+                (min, max)
+            );
+            let back = projection
+                .inverse(point)
+                .unwrap_or_else(|| panic!("{:?} doesn't map back", lat_lon.to_degrees()));
+            let error = lat_lon.angle_to(back);
+            assert!(
+                error < tolerance,
+                "{:?} maps back to {:?}",
+                lat_lon.to_degrees(),
+                back.to_degrees()
+            );
+        }
+        shown
+    }
+
+    #[test]
+    fn equirectangular_round_trips() {
+        let all = grid().count();
+        assert_eq!(assert_round_trip(&Equirectangular::default(), 1e-5), all);
+        let shifted = Equirectangular {
+            central_meridian: 1.0,
+        };
+        assert_eq!(assert_round_trip(&shifted, 1e-5), all);
+    }
+
+    #[test]
+    fn mollweide_round_trips() {
+        let all = grid().count();
+        assert_eq!(assert_round_trip(&Mollweide::default(), 1e-3), all);
+        let shifted = Mollweide {
+            central_meridian: -2.0,
+        };
+        assert_eq!(assert_round_trip(&shifted, 1e-3), all);
+    }
+
+    #[test]
+    fn robinson_round_trips() {
+        let all = grid().count();
+        assert_eq!(assert_round_trip(&Robinson::default(), 1e-4), all);
+    }
+
+    #[test]
+    fn orthographic_round_trips() {
+        for (lat, lon) in [(0.0, 0.0), (30.0, 45.0), (-90.0, 0.0)] {
+            let projection = Orthographic {
+                center: LatLon::from_degrees(lat, lon),
+            };
+            assert!(assert_round_trip(&projection, 1e-3) > 0);
+        }
+    }
+
+    #[test]
+    fn polar_stereographic_round_trips() {
+        for north in [true, false] {
+            let projection = PolarStereographic {
+                north,
+                boundary_lat: 0.0,
+            };
+            assert!(assert_round_trip(&projection, 1e-4) > 0);
+        }
+    }
+
+    #[test]
+    fn goode_homolosine_round_trips() {
+        let all = grid().count();
+        assert_eq!(assert_round_trip(&GoodeHomolosine, 1e-3), all);
+    }
+
+    #[test]
+    fn goode_homolosine_poles_touch_the_bounds() {
+        let (min, max) = GoodeHomolosine.bounds();
+        let north = GoodeHomolosine.forward(LatLon::from_degrees(90.0, 0.0));
+        let south = GoodeHomolosine.forward(LatLon::from_degrees(-90.0, 0.0));
+        assert!((north.unwrap().y - max.y).abs() < 1e-5);
+        assert!((south.unwrap().y - min.y).abs() < 1e-5);
+    }
+
+    #[test]
+    fn icosahedral_net_round_trips() {
+        let all = grid().count();
+        assert_eq!(assert_round_trip(&IcosahedralNet::new(), 1e-4), all);
+    }
+}
@@ -0,0 +1,68 @@
+use glam::Vec3;
+
+/// Small deterministic random number generator (SplitMix64).
+///
+/// Only integer arithmetic goes into the sequence, and the float helpers use
+/// `libm` rather than the platform's maths library, so a seed gives the same
+/// numbers on every platform, wasm32 included.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+    /// Independent sequence called `name` derived from `seed`. Each
+    /// generation stage draws from the stream named after it, so changing one
+    /// stage never reshuffles another.
+    pub fn stream(seed: u64, name: &str) -> Self {
+        // FNV-1a, which unlike std's hasher is fixed across versions
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        Rng::new(mix(seed ^ mix(hash)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        // the top 24 bits fill an f32 mantissa exactly
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `[min, max)`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Uniform integer in `[0, n)`.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        // multiply-shift keeps the bias negligible for any n we use
+        (((self.next_u64() >> 32) * n as u64) >> 32) as usize
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    /// Uniformly distributed direction.
+    pub fn unit_vec3(&mut self) -> Vec3 {
+        let z = self.range(-1.0, 1.0);
+        let angle = self.range(0.0, std::f32::consts::TAU);
+        let r = (1.0 - z * z).sqrt();
+        Vec3::new(r * libm::cosf(angle), r * libm::sinf(angle), z)
+    }
+}
+
+/// SplitMix64 finaliser, scrambling `x` into a well-distributed hash.
+pub fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,543 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, ensure};
+use glam::{DVec3, Vec3};
+
+use crate::{
+    icosphere::Icosphere,
+    planet::{CellField, GenerationParams, PlanetData, Plate},
+    timeline::{Frame, Timeline},
+    Result,
+};
+
+const MAGIC: &[u8; 8] = b"PLANETGN";
+/// Version of the layout written by [`write_planet`]. Version 2 widened the
+/// radius and mesh positions from `f32` to `f64`.
+pub const FORMAT_VERSION: u32 = 2;
+
+const PARAMS: &[u8; 4] = b"PARM";
+const TIME: &[u8; 4] = b"TIME";
+const MESH: &[u8; 4] = b"MESH";
+const PLATES: &[u8; 4] = b"PLAT";
+const FIELD: &[u8; 4] = b"FELD";
+// optional, so older readers skip it
+const TIMELINE: &[u8; 4] = b"tmln";
+const DONE: &[u8; 4] = b"DONE";
+
+// field kinds in FELD sections
+const SCALAR: u8 = 0;
+const ID: u8 = 1;
+const VECTOR: u8 = 2;
+
+/// Write the complete state of a planet in planetgen's binary format.
+///
+/// The file starts with the magic bytes `PLANETGN` and a `u32` format
+/// version, followed by sections up to a `DONE` section. Each section is a
+/// four byte tag, a `u64` payload length, the payload and a CRC-32 of the
+/// tag and payload. All numbers are little-endian.
+///
+/// As in PNG, a tag starting with a lowercase letter marks a section readers
+/// may skip when they don't know it, while an unknown uppercase tag is an
+/// error. Readers also ignore bytes after the end of a known section's
+/// payload, so later versions can append to existing sections.
+pub fn write_planet(planet: &PlanetData, mut w: impl Write) -> Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+    let mut params = Vec::new();
+    put_u64(&mut params, planet.params.seed);
+    put_f64(&mut params, planet.params.radius);
+    put_u32(&mut params, planet.params.subdivisions);
+    put_u32(&mut params, planet.params.plates);
+    write_section(&mut w, PARAMS, &params)?;
+
+    write_section(&mut w, TIME, &planet.time.to_le_bytes())?;
+
+    let sphere = &planet.sphere;
+    let mut mesh = Vec::with_capacity(12 + sphere.num_cells() * 24 + sphere.get_faces().len() * 12);
+    put_f64(&mut mesh, sphere.get_radius_f64());
+    put_u32(&mut mesh, sphere.num_cells() as u32);
+    for vertex in sphere.get_vertices() {
+        put_dvec3(&mut mesh, vertex.get_pos_f64());
+    }
+    put_u32(&mut mesh, sphere.get_faces().len() as u32);
+    for face in sphere.get_faces() {
+        for corner in face.get_corners() {
+            put_u32(&mut mesh, corner as u32);
+        }
+    }
+    write_section(&mut w, MESH, &mesh)?;
+
+    let mut plates = Vec::new();
+    put_u32(&mut plates, planet.plates.len() as u32);
+    for plate in &planet.plates {
+        put_vec3(&mut plates, plate.axis);
+        put_f32(&mut plates, plate.angular_speed);
+    }
+    write_section(&mut w, PLATES, &plates)?;
+
+    for (name, field) in planet.get_fields() {
+        let mut data = Vec::new();
+        put_u32(&mut data, name.len() as u32);
+        data.extend_from_slice(name.as_bytes());
+        match field {
+            CellField::Scalar(values) => {
+                data.push(SCALAR);
+                put_u32(&mut data, values.len() as u32);
+                values.iter().for_each(|&v| put_f32(&mut data, v));
+            }
+            CellField::Id(values) => {
+                data.push(ID);
+                put_u32(&mut data, values.len() as u32);
+                values.iter().for_each(|&v| put_u32(&mut data, v));
+            }
+            CellField::Vector(values) => {
+                data.push(VECTOR);
+                put_u32(&mut data, values.len() as u32);
+                values.iter().for_each(|&v| put_vec3(&mut data, v));
+            }
+        }
+        write_section(&mut w, FIELD, &data)?;
+    }
+
+    if !planet.timeline.is_empty() {
+        let mut data = Vec::new();
+        put_u32(&mut data, planet.timeline.len() as u32);
+        for frame in planet.timeline.get_frames() {
+            put_f64(&mut data, frame.time);
+            put_u32(&mut data, frame.plate.len() as u32);
+            frame.plate.iter().for_each(|&v| put_u32(&mut data, v));
+            frame.crust.iter().for_each(|&v| put_f32(&mut data, v));
+        }
+        write_section(&mut w, TIMELINE, &data)?;
+    }
+
+    write_section(&mut w, DONE, &[])?;
+    Ok(())
+}
+
+pub fn save_planet(planet: &PlanetData, path: impl AsRef<Path>) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_planet(planet, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Read a planet written by [`write_planet`], checking every section's
+/// checksum.
+pub fn read_planet(mut r: impl Read) -> Result<PlanetData> {
+    ensure!(&read_array(&mut r)? == MAGIC, "Not a planet file");
+    let version = u32::from_le_bytes(read_array(&mut r)?);
+    ensure!(
+        version <= FORMAT_VERSION,
+        "Planet file has format version {}, newer than the supported {}",
+        version,
+        FORMAT_VERSION
+    );
+
+    let mut params = None;
+    let mut time = 0.0;
+    let mut sphere = None;
+    let mut plates = Vec::new();
+    let mut fields = Vec::new();
+    let mut timeline = Timeline::new();
+    loop {
+        let tag = read_array(&mut r)?;
+        let length = u64::from_le_bytes(read_array(&mut r)?);
+        // read through `take` rather than allocating up front, so a corrupt
+        // length can't ask for more memory than the file holds
+        let mut payload = Vec::new();
+        (&mut r).take(length).read_to_end(&mut payload)?;
+        ensure!(payload.len() as u64 == length, "Planet file is truncated");
+        let checksum = u32::from_le_bytes(read_array(&mut r)?);
+        ensure!(
+            checksum == crc32(&[&tag, &payload]),
+            "Checksum mismatch in section {}",
+            tag_name(&tag)
+        );
+
+        let mut data = Payload {
+            tag,
+            bytes: &payload,
+        };
+        match &tag {
+            PARAMS => {
+                params = Some(GenerationParams {
+                    seed: data.u64()?,
+                    radius: data.float(version)?,
+                    subdivisions: data.u32()?,
+                    plates: data.u32()?,
+                })
+            }
+            TIME => time = data.f64()?,
+            MESH => sphere = Some(read_mesh(&mut data, version)?),
+            PLATES => {
+                let count = data.u32()?;
+                plates = (0..count)
+                    .map(|_| {
+                        Ok(Plate {
+                            axis: data.vec3()?,
+                            angular_speed: data.f32()?,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+            }
+            FIELD => fields.push(read_field(&mut data)?),
+            TIMELINE => timeline = read_timeline(&mut data)?,
+            DONE => break,
+            tag if tag[0].is_ascii_lowercase() => {}
+            tag => bail!("Planet file has unknown section {}", tag_name(tag)),
+        }
+    }
+
+    let (params, sphere) = match (params, sphere) {
+        (Some(params), Some(sphere)) => (params, sphere),
+        _ => bail!("Planet file is missing its parameters or mesh"),
+    };
+    let mut planet = PlanetData::from_sphere(params, sphere);
+    planet.time = time;
+    planet.plates = plates;
+    for frame in timeline.get_frames() {
+        ensure!(
+            frame.plate.len() == planet.sphere.num_cells(),
+            "Timeline frame has {} values for {} cells",
+            frame.plate.len(),
+            planet.sphere.num_cells()
+        );
+    }
+    planet.timeline = timeline;
+    let mut names = HashSet::new();
+    for (name, field) in fields {
+        ensure!(
+            field.len() == planet.sphere.num_cells(),
+            "Field {} has {} values for {} cells",
+            name,
+            field.len(),
+            planet.sphere.num_cells()
+        );
+        ensure!(names.insert(name.clone()), "Field {} appears twice", name);
+        planet.insert_field(name, field);
+    }
+    Ok(planet)
+}
+
+pub fn load_planet(path: impl AsRef<Path>) -> Result<PlanetData> {
+    read_planet(BufReader::new(File::open(path)?))
+}
+
+fn read_array<const N: usize>(mut r: impl Read) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    match r.read_exact(&mut bytes) {
+        Ok(()) => Ok(bytes),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => bail!("Planet file is truncated"),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn read_mesh(data: &mut Payload, version: u32) -> Result<Icosphere> {
+    let radius = data.float(version)?;
+    ensure!(radius > 0.0, "Mesh has a radius of {}", radius);
+    let num_vertices = data.u32()? as usize;
+    let positions = (0..num_vertices)
+        .map(|_| {
+            let [x, y, z] = [(); 3].map(|_| data.float(version));
+            Ok(DVec3::new(x?, y?, z?))
+        })
+        .collect::<Result<_>>()?;
+    let num_faces = data.u32()? as usize;
+    let faces = (0..num_faces)
+        .map(|_| {
+            let corners = [data.u32()?, data.u32()?, data.u32()?].map(|c| c as usize);
+            ensure!(
+                corners.iter().all(|&c| c < num_vertices),
+                "Mesh has a face {:?} with corners past its {} vertices",
+                corners,
+                num_vertices
+            );
+            Ok(corners)
+        })
+        .collect::<Result<_>>()?;
+    Ok(Icosphere::from_mesh_f64(radius, positions, faces))
+}
+
+fn read_field(data: &mut Payload) -> Result<(String, CellField)> {
+    let length = data.u32()? as usize;
+    let name = String::from_utf8(data.bytes(length)?.to_vec())?;
+    let kind = data.u8()?;
+    let count = data.u32()?;
+    let field = match kind {
+        SCALAR => CellField::Scalar((0..count).map(|_| data.f32()).collect::<Result<_>>()?),
+        ID => CellField::Id((0..count).map(|_| data.u32()).collect::<Result<_>>()?),
+        VECTOR => CellField::Vector((0..count).map(|_| data.vec3()).collect::<Result<_>>()?),
+        kind => bail!("Field {} has unknown kind {}", name, kind),
+    };
+    Ok((name, field))
+}
+
+fn read_timeline(data: &mut Payload) -> Result<Timeline> {
+    let mut timeline = Timeline::new();
+    for _ in 0..data.u32()? {
+        let time = data.f64()?;
+        let count = data.u32()?;
+        let plate = (0..count).map(|_| data.u32()).collect::<Result<_>>()?;
+        let crust = (0..count).map(|_| data.f32()).collect::<Result<_>>()?;
+        timeline.push(Frame { time, plate, crust });
+    }
+    Ok(timeline)
+}
+
+fn write_section(mut w: impl Write, tag: &[u8; 4], payload: &[u8]) -> Result<()> {
+    w.write_all(tag)?;
+    w.write_all(&(payload.len() as u64).to_le_bytes())?;
+    w.write_all(payload)?;
+    w.write_all(&crc32(&[tag, payload]).to_le_bytes())?;
+    Ok(())
+}
+
+fn put_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+fn put_u64(data: &mut Vec<u8>, value: u64) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+fn put_f32(data: &mut Vec<u8>, value: f32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+fn put_f64(data: &mut Vec<u8>, value: f64) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+fn put_vec3(data: &mut Vec<u8>, value: Vec3) {
+    value.to_array().iter().for_each(|&v| put_f32(data, v));
+}
+fn put_dvec3(data: &mut Vec<u8>, value: DVec3) {
+    value.to_array().iter().for_each(|&v| put_f64(data, v));
+}
+
+// remaining bytes of a section being parsed
+struct Payload<'a> {
+    tag: [u8; 4],
+    bytes: &'a [u8],
+}
+impl<'a> Payload<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.bytes.len() >= n,
+            "Section {} is too short",
+            tag_name(&self.tag)
+        );
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+    fn vec3(&mut self) -> Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+    // radius and mesh positions, f32 before version 2
+    fn float(&mut self, version: u32) -> Result<f64> {
+        if version < 2 {
+            Ok(self.f32()? as f64)
+        } else {
+            self.f64()
+        }
+    }
+}
+
+fn tag_name(tag: &[u8; 4]) -> String {
+    String::from_utf8_lossy(tag).into_owned()
+}
+
+// the CRC-32 used by zip and png
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    let mut crc = !0u32;
+    for &byte in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::generate;
+
+    fn planet() -> PlanetData {
+        let mut planet = generate(&GenerationParams {
+            seed: 9,
+            subdivisions: 2,
+            plates: 4,
+            ..Default::default()
+        });
+        let plate = planet.get::<u32>("plate").unwrap().clone();
+        let crust = planet.get::<f32>("crust").unwrap().clone();
+        planet.timeline.push(Frame {
+            time: 0.5,
+            plate,
+            crust,
+        });
+        planet
+    }
+
+    fn bytes(planet: &PlanetData) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_planet(planet, &mut bytes).unwrap();
+        bytes
+    }
+
+    // the file with `section` inserted before its DONE section
+    fn with_section(mut file: Vec<u8>, tag: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut section = Vec::new();
+        write_section(&mut section, tag, payload).unwrap();
+        let done = file.len() - 16;
+        file.splice(done..done, section);
+        file
+    }
+
+    #[test]
+    fn round_trips() {
+        let planet = planet();
+        let file = bytes(&planet);
+        let read = read_planet(&file[..]).unwrap();
+        assert_eq!(read.params, planet.params);
+        assert_eq!(read.time, planet.time);
+        assert_eq!(read.plates, planet.plates);
+        assert_eq!(read.timeline, planet.timeline);
+        assert_eq!(read.get_scalar("elevation"), planet.get_scalar("elevation"));
+        assert_eq!(read.get_vectors("velocity"), planet.get_vectors("velocity"));
+        assert_eq!(bytes(&read), file);
+    }
+
+    #[test]
+    fn rejects_checksum_mismatches() {
+        let mut file = bytes(&planet());
+        // a byte of the first section's payload, just past its header
+        file[12 + 8 + 4] ^= 1;
+        let error = read_planet(&file[..]).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Checksum mismatch in section PARM"));
+    }
+
+    #[test]
+    fn skips_unknown_lowercase_sections() {
+        let planet = planet();
+        let file = with_section(bytes(&planet), b"xtra", b"anything");
+        assert_eq!(bytes(&read_planet(&file[..]).unwrap()), bytes(&planet));
+
+        let file = with_section(bytes(&planet), b"XTRA", b"anything");
+        let error = read_planet(&file[..]).unwrap_err();
+        assert!(error.to_string().contains("unknown section XTRA"));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let file = bytes(&planet());
+        for length in [
+            0,
+            4,
+            12,
+            20,
+            file.len() / 2,
+            file.len() - 17,
+            file.len() - 1,
+        ] {
+            let error = read_planet(&file[..length]).unwrap_err();
+            assert!(
+                error.to_string().contains("truncated"),
+                "{} bytes: {}",
+                length,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn reads_version_1_files() {
+        // version 1 stored the radius and mesh positions as f32
+        let planet = planet();
+        let sphere = &planet.sphere;
+        let mut file = MAGIC.to_vec();
+        put_u32(&mut file, 1);
+        let mut params = Vec::new();
+        put_u64(&mut params, planet.params.seed);
+        put_f32(&mut params, planet.params.radius as f32);
+        put_u32(&mut params, planet.params.subdivisions);
+        put_u32(&mut params, planet.params.plates);
+        write_section(&mut file, PARAMS, &params).unwrap();
+        write_section(&mut file, TIME, &planet.time.to_le_bytes()).unwrap();
+        let mut mesh = Vec::new();
+        put_f32(&mut mesh, sphere.get_radius());
+        put_u32(&mut mesh, sphere.num_cells() as u32);
+        for vertex in sphere.get_vertices() {
+            put_vec3(&mut mesh, vertex.get_pos_f64().as_vec3());
+        }
+        put_u32(&mut mesh, sphere.get_faces().len() as u32);
+        for face in sphere.get_faces() {
+            face.get_corners()
+                .iter()
+                .for_each(|&c| put_u32(&mut mesh, c as u32));
+        }
+        write_section(&mut file, MESH, &mesh).unwrap();
+        write_section(&mut file, DONE, &[]).unwrap();
+
+        let read = read_planet(&file[..]).unwrap();
+        assert_eq!(read.params, planet.params);
+        assert_eq!(read.time, planet.time);
+        assert_eq!(read.sphere.get_radius_f64(), sphere.get_radius_f64());
+        let corners = |sphere: &Icosphere| {
+            let faces = sphere.get_faces().iter();
+            faces.map(|face| face.get_corners()).collect::<Vec<_>>()
+        };
+        assert_eq!(corners(&read.sphere), corners(sphere));
+        for (read, vertex) in read.sphere.get_vertices().iter().zip(sphere.get_vertices()) {
+            let pos = vertex.get_pos_f64().as_vec3().as_dvec3();
+            assert_eq!(read.get_pos_f64(), pos);
+        }
+    }
+
+    #[test]
+    fn crc32_matches_the_standard() {
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xcbf4_3926);
+    }
+}
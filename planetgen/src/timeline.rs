@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    coords::CellLocator,
+    export::{map::render_color, ColorRamp, Interpolation},
+    field::Field,
+    icosphere::Icosphere,
+    projection::{Equirectangular, Projection},
+    Result,
+};
+
+/// Plate ids and crust of a planet through its drift simulation, as
+/// recorded by [`Drift`](crate::generate::Drift), oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timeline {
+    frames: Vec<Frame>,
+}
+
+/// The planet at one moment of a [`Timeline`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frame {
+    /// Simulation time of the frame.
+    pub time: f64,
+    pub plate: Field<u32>,
+    /// Height of the crust, before later stages raise mountains and wear
+    /// them down.
+    #[cfg_attr(feature = "serde", serde(alias = "elevation"))]
+    pub crust: Field<f32>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+    pub fn get_frames(&self) -> &[Frame] {
+        &self.frames
+    }
+    /// Keep only the first `len` frames.
+    pub fn truncate(&mut self, len: usize) {
+        self.frames.truncate(len);
+    }
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Latest frame at or before `time`, or the first frame.
+    pub fn get_frame_at(&self, time: f64) -> Option<&Frame> {
+        let after = self.frames.partition_point(|frame| frame.time <= time);
+        self.frames.get(after.saturating_sub(1))
+    }
+
+    /// Lowest and highest crust over every frame, so all frames can be
+    /// coloured alike.
+    pub fn crust_range(&self) -> (f32, f32) {
+        self.frames
+            .iter()
+            .fold((0.0f32, 0.0f32), |(lo, hi), frame| {
+                let min = frame.crust.min().unwrap_or(lo);
+                let max = frame.crust.max().unwrap_or(hi);
+                (lo.min(min), hi.max(max))
+            })
+    }
+
+    /// Terrain colours over [`Timeline::crust_range`], reaching as high
+    /// above sea level as below it so flat continents don't show as peaks.
+    pub fn get_ramp(&self) -> ColorRamp {
+        let (min, max) = self.crust_range();
+        ColorRamp::terrain(min, 0.0, max.max(-min))
+    }
+
+    /// Extend every frame over the cells [`Icosphere::subdivide`] added.
+    pub fn subdivide(&mut self, parents: &[[usize; 2]]) {
+        for frame in &mut self.frames {
+            frame.plate.subdivide(parents);
+            frame.crust.subdivide(parents);
+        }
+    }
+
+    /// Write every frame as an equirectangular map `width` pixels wide to
+    /// `frame-00000.png`, `frame-00001.png` and so on in `dir`, ready for a
+    /// video encoder.
+    pub fn save_frames(
+        &self,
+        sphere: &Icosphere,
+        dir: impl AsRef<Path>,
+        width: u32,
+    ) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let locator = CellLocator::new(sphere);
+        let ramp = self.get_ramp();
+        let projection = Equirectangular::default();
+        let height = projection.height_for_width(width);
+        let mut paths = Vec::with_capacity(self.frames.len());
+        for (i, frame) in self.frames.iter().enumerate() {
+            let map = render_color(
+                &locator,
+                &frame.colors(sphere, &ramp),
+                &projection,
+                width,
+                height,
+                Interpolation::Nearest,
+            );
+            let path = dir.join(format!("frame-{:05}.png", i));
+            map.save(&path)
+                .with_context(|| format!("Writing {}", path.display()))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+impl Frame {
+    /// Colour of every cell, its crust on `ramp` darkened where it
+    /// borders another plate.
+    pub fn colors(&self, sphere: &Icosphere, ramp: &ColorRamp) -> Vec<[f32; 3]> {
+        (0..sphere.num_cells())
+            .map(|cell| {
+                let color = ramp.sample(self.crust[cell]);
+                let boundary = sphere
+                    .get_neighbors(cell)
+                    .iter()
+                    .any(|&n| self.plate[n] != self.plate[cell]);
+                if boundary {
+                    color.map(|c| c * 0.5)
+                } else {
+                    color
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(sphere: &Icosphere, time: f64) -> Frame {
+        let cells = sphere.num_cells();
+        Frame {
+            time,
+            plate: (0..cells).map(|cell| (cell % 3) as u32).collect(),
+            crust: (0..cells).map(|cell| cell as f32 - time as f32).collect(),
+        }
+    }
+
+    fn timeline(sphere: &Icosphere) -> Timeline {
+        let mut timeline = Timeline::new();
+        for time in [0.0, 1.0, 2.0] {
+            timeline.push(frame(sphere, time));
+        }
+        timeline
+    }
+
+    #[test]
+    fn frames_are_found_by_time() {
+        let timeline = timeline(&Icosphere::new(1.0));
+        let time_at = |time| timeline.get_frame_at(time).unwrap().time;
+        assert_eq!(time_at(-1.0), 0.0);
+        assert_eq!(time_at(1.0), 1.0);
+        assert_eq!(time_at(1.5), 1.0);
+        assert_eq!(time_at(5.0), 2.0);
+        assert!(Timeline::new().get_frame_at(0.0).is_none());
+    }
+
+    #[test]
+    fn crust_range_covers_every_frame() {
+        assert_eq!(Timeline::new().crust_range(), (0.0, 0.0));
+        let timeline = timeline(&Icosphere::new(1.0));
+        assert_eq!(timeline.crust_range(), (-2.0, 11.0));
+    }
+
+    #[test]
+    fn subdividing_extends_every_frame() {
+        let mut sphere = Icosphere::new(1.0);
+        let mut timeline = timeline(&sphere);
+        let parents = sphere.subdivide();
+        timeline.subdivide(&parents);
+        for frame in timeline.get_frames() {
+            assert_eq!(frame.plate.len(), sphere.num_cells());
+            assert_eq!(frame.crust.len(), sphere.num_cells());
+            for (i, &[a, b]) in parents.iter().enumerate() {
+                let cell = 12 + i;
+                let mean = (frame.crust[a] + frame.crust[b]) / 2.0;
+                assert!((frame.crust[cell] - mean).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn frames_are_saved_as_numbered_maps() {
+        let sphere = Icosphere::subdivided(1.0, 2);
+        let timeline = timeline(&sphere);
+        let dir = std::env::temp_dir().join(format!("planetgen-frames-{}", std::process::id()));
+        let paths = timeline.save_frames(&sphere, &dir, 64).unwrap();
+        assert_eq!(paths.len(), 3);
+        let height = Equirectangular::default().height_for_width(64);
+        for (i, path) in paths.iter().enumerate() {
+            assert_eq!(path, &dir.join(format!("frame-{:05}.png", i)));
+            let map = image::open(path).unwrap();
+            assert_eq!((map.width(), map.height()), (64, height));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
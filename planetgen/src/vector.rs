@@ -0,0 +1,525 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use glam::Vec3;
+
+use crate::{icosphere::Icosphere, parallel::map_cells};
+
+/// Line across the surface of the planet through unit direction vectors.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Polyline {
+    pub points: Vec<Vec3>,
+    /// The last point connects back to the first.
+    pub closed: bool,
+}
+
+/// Contour lines where a per-cell field crosses `level`, traced over the
+/// triangles of the mesh.
+///
+/// Every contour is a closed loop with values above `level` on its left
+/// when seen from outside the planet, so coastlines run counter-clockwise
+/// around land.
+pub fn contours(sphere: &Icosphere, values: &[f32], level: f32) -> Vec<Polyline> {
+    assert_eq!(values.len(), sphere.num_cells());
+    let above = |cell: usize| values[cell] >= level;
+    let key = |a: usize, b: usize| (a.min(b), a.max(b));
+
+    // one segment per face crossing the level, from edge to edge, ordered
+    // so every run traces the loops from the same starting points
+    let mut segments = BTreeMap::new();
+    for face in sphere.get_faces() {
+        let corners = face.get_corners();
+        let count = corners.iter().filter(|&&c| above(c)).count();
+        if count == 0 || count == 3 {
+            continue;
+        }
+        // find the corner on its own side of the level
+        let odd = (0..3).find(|&i| above(corners[i]) == (count == 1)).unwrap();
+        let k = corners[odd];
+        let next = corners[(odd + 1) % 3];
+        let prev = corners[(odd + 2) % 3];
+        let (from, to) = if count == 1 {
+            (key(k, next), key(prev, k))
+        } else {
+            (key(prev, k), key(k, next))
+        };
+        segments.insert(from, to);
+    }
+
+    let mut lines = Vec::new();
+    while let Some(&start) = segments.keys().next() {
+        let mut points = Vec::new();
+        let mut edge = start;
+        while let Some(next) = segments.remove(&edge) {
+            points.push(crossing(sphere, values, level, edge.0, edge.1));
+            edge = next;
+        }
+        lines.push(Polyline {
+            points,
+            closed: true,
+        });
+    }
+    lines
+}
+
+/// Parts of the mesh triangles where a per-cell field is at least `level`,
+/// each a small counter-clockwise polygon, so that together they fill the
+/// area inside the loops traced by [`contours`].
+pub fn pieces_above(sphere: &Icosphere, values: &[f32], level: f32) -> Vec<Vec<Vec3>> {
+    assert_eq!(values.len(), sphere.num_cells());
+    let above = |cell: usize| values[cell] >= level;
+    let mut pieces = Vec::new();
+    for face in sphere.get_faces() {
+        let corners = face.get_corners();
+        if !corners.iter().any(|&c| above(c)) {
+            continue;
+        }
+        // clip the triangle to the part above the level
+        let mut piece = Vec::with_capacity(4);
+        for i in 0..3 {
+            let (a, b) = (corners[i], corners[(i + 1) % 3]);
+            if above(a) {
+                piece.push(sphere.get_position(a).normalize());
+            }
+            if above(a) != above(b) {
+                piece.push(crossing(sphere, values, level, a, b));
+            }
+        }
+        pieces.push(piece);
+    }
+    pieces
+}
+
+// point where the field reaches `level` along the edge between two cells,
+// interpolated in a fixed order so both faces sharing the edge agree
+fn crossing(sphere: &Icosphere, values: &[f32], level: f32, a: usize, b: usize) -> Vec3 {
+    let (a, b) = (a.min(b), a.max(b));
+    let t = (level - values[a]) / (values[b] - values[a]);
+    sphere
+        .get_position(a)
+        .lerp(sphere.get_position(b), t)
+        .normalize()
+}
+
+/// How two plates move relative to each other across their boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BoundaryKind {
+    Convergent,
+    Divergent,
+    Transform,
+    /// Plate motion wasn't provided.
+    Unknown,
+}
+impl BoundaryKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BoundaryKind::Convergent => "convergent",
+            BoundaryKind::Divergent => "divergent",
+            BoundaryKind::Transform => "transform",
+            BoundaryKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// Section of the border between two plates with a single kind of motion.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Boundary {
+    pub line: Polyline,
+    pub plates: (u32, u32),
+    pub kind: BoundaryKind,
+}
+
+/// Borders between cells with different plate ids, following the edges of
+/// the cells rather than the triangles between them.
+///
+/// When surface velocities are given for each cell, boundaries are split
+/// and classified by whether the plates approach, separate or slide past
+/// each other there.
+pub fn plate_boundaries(
+    sphere: &Icosphere,
+    plate_ids: &[u32],
+    velocities: Option<&[Vec3]>,
+) -> Vec<Boundary> {
+    assert_eq!(plate_ids.len(), sphere.num_cells());
+    // ordered by plates so the boundaries come out in the same order every run
+    let mut groups = BTreeMap::<(u32, u32), Vec<(Corner, Corner)>>::new();
+    let mut motions = HashMap::new();
+    for cell in 0..sphere.num_cells() {
+        let ring = sphere.get_neighbors(cell);
+        for i in 0..ring.len() {
+            let other = ring[i];
+            // visit each pair of cells once, from the lower plate id
+            if plate_ids[cell] >= plate_ids[other] {
+                continue;
+            }
+            // the cell edge runs between the centres of the two faces
+            // sharing the mesh edge
+            let before = ring[(i + ring.len() - 1) % ring.len()];
+            let after = ring[(i + 1) % ring.len()];
+            let segment = (
+                Corner::new(cell, before, other),
+                Corner::new(cell, other, after),
+            );
+            groups
+                .entry((plate_ids[cell], plate_ids[other]))
+                .or_default()
+                .push(segment);
+            if let Some(v) = velocities {
+                // corners run counter-clockwise around `cell`, so in this
+                // order the lower plate is on the left
+                let motion = v[other] - v[cell];
+                motions.insert(segment, (motion, true));
+                motions.insert((segment.1, segment.0), (motion, false));
+            }
+        }
+    }
+
+    let mut boundaries = Vec::new();
+    for (plates, segments) in groups {
+        for corners in chain(&segments) {
+            let closed = corners.len() > 2 && corners.first() == corners.last();
+            let points: Vec<Vec3> = corners.iter().map(|c| c.position(sphere)).collect();
+            let kinds: Vec<BoundaryKind> = (0..corners.len() - 1)
+                .map(|i| match motions.get(&(corners[i], corners[i + 1])) {
+                    Some(&(motion, left)) => {
+                        // follow the overall direction of the line rather
+                        // than the zigzag of individual cell edges
+                        let from = points[i.saturating_sub(2)];
+                        let to = points[(i + 3).min(points.len() - 1)];
+                        let up = (points[i] + points[i + 1]).normalize();
+                        let across = (to - from).cross(up).normalize_or_zero();
+                        classify(motion, if left { across } else { -across })
+                    }
+                    None => BoundaryKind::Unknown,
+                })
+                .collect();
+            let kinds = smooth(&kinds);
+
+            // split into runs of a single kind
+            let first = boundaries.len();
+            let mut start = 0;
+            for end in 1..=kinds.len() {
+                if end < kinds.len() && kinds[end] == kinds[start] {
+                    continue;
+                }
+                boundaries.push(Boundary {
+                    line: Polyline {
+                        points: points[start..=end].to_vec(),
+                        closed: false,
+                    },
+                    plates,
+                    kind: kinds[start],
+                });
+                start = end;
+            }
+            // a loop of one kind doesn't need its repeated first point
+            if closed && boundaries.len() == first + 1 {
+                let line = &mut boundaries[first].line;
+                line.points.pop();
+                line.closed = true;
+            }
+        }
+    }
+    boundaries
+}
+
+// majority kind over a few neighbouring segments, since the zigzag of cell
+// edges makes the kind of single segments flicker
+fn smooth(kinds: &[BoundaryKind]) -> Vec<BoundaryKind> {
+    const RADIUS: usize = 2;
+    (0..kinds.len())
+        .map(|i| {
+            let window = &kinds[i.saturating_sub(RADIUS)..(i + RADIUS + 1).min(kinds.len())];
+            let count = |k: &BoundaryKind| window.iter().filter(|&w| w == k).count();
+            // prefer the segment's own kind on ties
+            window
+                .iter()
+                .copied()
+                .max_by_key(|k| (count(k), *k == kinds[i]))
+                .unwrap()
+        })
+        .collect()
+}
+
+// compare the motion of the second plate relative to the first with the
+// direction pointing across the boundary from the first to the second
+fn classify(relative_velocity: Vec3, across: Vec3) -> BoundaryKind {
+    let normal_speed = relative_velocity.dot(across);
+    let tangent_speed = (relative_velocity - across * normal_speed).length();
+    if tangent_speed > normal_speed.abs() {
+        BoundaryKind::Transform
+    } else if normal_speed < 0.0 {
+        BoundaryKind::Convergent
+    } else {
+        BoundaryKind::Divergent
+    }
+}
+
+// corner of a cell, at the centre of one of the mesh faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner([usize; 3]);
+impl Corner {
+    fn new(a: usize, b: usize, c: usize) -> Self {
+        let mut cells = [a, b, c];
+        cells.sort_unstable();
+        Corner(cells)
+    }
+    fn position(&self, sphere: &Icosphere) -> Vec3 {
+        self.0
+            .iter()
+            .fold(Vec3::ZERO, |sum, &c| sum + sphere.get_position(c))
+            .normalize()
+    }
+}
+
+/// Outlines of the area covered by polygons that meet along shared edges,
+/// such as those from [`pieces_above`] or [`cell_outline`].
+///
+/// Polygons must be counter-clockwise seen from outside and shared corners
+/// bit for bit equal. Edges two polygons share cancel out, leaving
+/// counter-clockwise rings around each area and clockwise rings around
+/// its holes.
+pub fn merge_polygons(polygons: &[Vec<Vec3>]) -> Vec<Vec<Vec3>> {
+    let key = |p: Vec3| p.to_array().map(f32::to_bits);
+    let mut edges = HashSet::new();
+    for polygon in polygons {
+        for i in 0..polygon.len() {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            if !edges.remove(&(key(b), key(a))) {
+                edges.insert((key(a), key(b)));
+            }
+        }
+    }
+    // follow the edges left over from each corner, in the order of the
+    // polygons so the rings come out the same every run
+    let mut from = HashMap::<_, Vec<Vec3>>::new();
+    for polygon in polygons {
+        for i in 0..polygon.len() {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            if edges.contains(&(key(a), key(b))) {
+                from.entry(key(a)).or_default().push(b);
+            }
+        }
+    }
+    let mut rings = Vec::new();
+    for &start in polygons.iter().flatten() {
+        while let Some(&next) = from.get(&key(start)).and_then(|n| n.first()) {
+            let mut ring = vec![start];
+            let mut point = next;
+            take(&mut from, key(start), next);
+            while key(point) != key(start) {
+                ring.push(point);
+                let next = from[&key(point)][0];
+                take(&mut from, key(point), next);
+                point = next;
+            }
+            rings.push(ring);
+        }
+    }
+    rings
+}
+
+// remove the edge from `a` to `b`
+fn take(from: &mut HashMap<[u32; 3], Vec<Vec3>>, a: [u32; 3], b: Vec3) {
+    let ends = from.get_mut(&a).unwrap();
+    let i = ends.iter().position(|&e| e == b).unwrap();
+    ends.remove(i);
+}
+
+/// Corners of the polygon outlining a cell, counter-clockwise seen from
+/// outside.
+pub fn cell_outline(sphere: &Icosphere, cell: usize) -> Vec<Vec3> {
+    let ring = sphere.get_neighbors(cell);
+    (0..ring.len())
+        .map(|i| Corner::new(cell, ring[i], ring[(i + 1) % ring.len()]).position(sphere))
+        .collect()
+}
+
+// join undirected segments into the longest runs possible, splitting at
+// points where more than two segments meet
+fn chain<T: Copy + Eq + std::hash::Hash>(segments: &[(T, T)]) -> Vec<Vec<T>> {
+    let mut links = HashMap::<T, Vec<usize>>::new();
+    for (i, &(a, b)) in segments.iter().enumerate() {
+        links.entry(a).or_default().push(i);
+        links.entry(b).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    let walk = |start: T, first: usize, used: &mut Vec<bool>| {
+        let mut points = vec![start];
+        let mut point = start;
+        let mut segment = first;
+        loop {
+            used[segment] = true;
+            let (a, b) = segments[segment];
+            point = if a == point { b } else { a };
+            points.push(point);
+            let link = &links[&point];
+            if link.len() != 2 {
+                break;
+            }
+            match link.iter().find(|&&s| !used[s]) {
+                Some(&s) => segment = s,
+                None => break,
+            }
+        }
+        points
+    };
+
+    let mut lines = Vec::new();
+    // start from ends and junctions first so open lines aren't cut short,
+    // finding them in the order of the segments rather than the map's
+    for &point in segments.iter().flat_map(|(a, b)| [a, b]) {
+        let link = &links[&point];
+        if link.len() != 2 {
+            for &s in link {
+                if !used[s] {
+                    lines.push(walk(point, s, &mut used));
+                }
+            }
+        }
+    }
+    // whatever remains forms closed loops
+    for s in 0..segments.len() {
+        if !used[s] {
+            lines.push(walk(segments[s].0, s, &mut used));
+        }
+    }
+    lines
+}
+
+/// Neighbour each cell drains into: its steepest downhill neighbour, or
+/// `None` for local minima and cells below `sea_level`.
+pub fn drainage(sphere: &Icosphere, elevation: &[f32], sea_level: f32) -> Vec<Option<usize>> {
+    assert_eq!(elevation.len(), sphere.num_cells());
+    map_cells(sphere.num_cells(), |cell| {
+        if elevation[cell] < sea_level {
+            return None;
+        }
+        let pos = sphere.get_position(cell);
+        sphere
+            .get_neighbors(cell)
+            .iter()
+            .map(|&n| {
+                let drop = elevation[cell] - elevation[n];
+                (n, drop / (sphere.get_position(n) - pos).length())
+            })
+            .filter(|&(_, slope)| slope > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(n, _)| n)
+    })
+}
+
+/// Number of cells draining through each cell, including itself.
+pub fn flow_accumulation(downstream: &[Option<usize>]) -> Vec<f32> {
+    let mut flow = vec![1.0; downstream.len()];
+    for cell in upstream_first(downstream) {
+        if let Some(d) = downstream[cell] {
+            flow[d] += flow[cell];
+        }
+    }
+    flow
+}
+
+// cells ordered so that every cell comes after all cells draining into it
+fn upstream_first(downstream: &[Option<usize>]) -> Vec<usize> {
+    let mut inflow = vec![0; downstream.len()];
+    for d in downstream.iter().flatten() {
+        inflow[*d] += 1;
+    }
+    let mut ready: Vec<usize> = (0..downstream.len()).filter(|&c| inflow[c] == 0).collect();
+    let mut order = Vec::with_capacity(downstream.len());
+    while let Some(cell) = ready.pop() {
+        order.push(cell);
+        if let Some(d) = downstream[cell] {
+            inflow[d] -= 1;
+            if inflow[d] == 0 {
+                ready.push(d);
+            }
+        }
+    }
+    order
+}
+
+/// Stretch of river with a single Strahler stream order.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct River {
+    pub line: Polyline,
+    pub order: u32,
+    /// Flow at the downstream end of the stretch.
+    pub flow: f32,
+}
+
+/// River network through every cell whose flow is at least `min_flow`.
+///
+/// Stretches end where they join a river of a different order, with the
+/// last point at the confluence.
+pub fn rivers(
+    sphere: &Icosphere,
+    downstream: &[Option<usize>],
+    flow: &[f32],
+    min_flow: f32,
+) -> Vec<River> {
+    assert_eq!(downstream.len(), sphere.num_cells());
+    let is_river = |cell: usize| flow[cell] >= min_flow;
+
+    // strahler order: sources are 1, and two streams of equal order
+    // join into one of the next order
+    let mut order = vec![0u32; downstream.len()];
+    let mut highest = vec![(0u32, 0u32); downstream.len()];
+    for cell in upstream_first(downstream) {
+        if !is_river(cell) {
+            continue;
+        }
+        let (max, count) = highest[cell];
+        order[cell] = match (max, count) {
+            (0, _) => 1,
+            (max, 1) => max,
+            (max, _) => max + 1,
+        };
+        if let Some(d) = downstream[cell] {
+            let h = &mut highest[d];
+            if order[cell] > h.0 {
+                *h = (order[cell], 1);
+            } else if order[cell] == h.0 {
+                h.1 += 1;
+            }
+        }
+    }
+
+    // a stretch starts wherever no upstream cell has the same order
+    let mut continued = vec![false; downstream.len()];
+    for cell in 0..downstream.len() {
+        if let Some(d) = downstream[cell] {
+            if is_river(cell) && order[d] == order[cell] {
+                continued[d] = true;
+            }
+        }
+    }
+
+    let mut rivers = Vec::new();
+    for start in (0..downstream.len()).filter(|&c| is_river(c) && !continued[c]) {
+        let mut points = vec![sphere.get_position(start).normalize()];
+        let mut cell = start;
+        while let Some(d) = downstream[cell] {
+            points.push(sphere.get_position(d).normalize());
+            if !is_river(d) || order[d] != order[start] {
+                break;
+            }
+            cell = d;
+        }
+        if points.len() > 1 {
+            rivers.push(River {
+                line: Polyline {
+                    points,
+                    closed: false,
+                },
+                order: order[start],
+                flow: flow[cell],
+            });
+        }
+    }
+    rivers
+}
@@ -1,23 +1,309 @@
-use log;
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, rc::Rc};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use glam::{DVec3, Mat4, Vec3};
+use instant::Instant;
+use planetgen::{
+    export::{mesh::MeshData, ColorRamp},
+    generate::{standard_pipeline, Drift},
+    pipeline::{CancelToken, Cancelled, Pipeline, Progress},
+    planet::{GenerationParams, PlanetData},
+    Result,
+};
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
 
-use super::graphics::engine::Engine;
+use super::graphics::{self, camera::CameraController, engine::Engine};
+
+const TITLE: &str = "planetgen";
+
+// the standard stages, recording every step of the drift
+fn pipeline(cancel: CancelToken) -> Pipeline {
+    let mut pipeline = standard_pipeline();
+    let drift = Drift {
+        record_every: 1,
+        ..Default::default()
+    };
+    pipeline
+        .replace("drift", drift)
+        .expect("the standard pipeline drifts");
+    pipeline.set_cancel_token(cancel);
+    pipeline
+}
+
+/// Generation running on its own thread, sending its progress and the
+/// planet back.
+#[cfg(not(target_arch = "wasm32"))]
+struct Worker {
+    updates: Receiver<Update>,
+}
+#[cfg(not(target_arch = "wasm32"))]
+enum Update {
+    Progress(Progress),
+    Done(Result<PlanetData>),
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl Worker {
+    fn start(params: GenerationParams, cancel: CancelToken) -> Self {
+        let (sender, updates) = mpsc::channel();
+        thread::spawn(move || {
+            let mut pipeline = pipeline(cancel);
+            let progress = sender.clone();
+            pipeline.on_progress(move |p| {
+                // the window may have closed
+                let _ = progress.send(Update::Progress(p.clone()));
+            });
+            let mut planet = PlanetData::new(params);
+            let result = pipeline.run(&mut planet).map(|()| planet);
+            let _ = sender.send(Update::Done(result));
+        });
+        Worker { updates }
+    }
+
+    /// The latest progress since the last poll, and the planet once done.
+    fn poll(&mut self) -> (Option<Progress>, Option<Result<PlanetData>>) {
+        let mut progress = None;
+        loop {
+            match self.updates.try_recv() {
+                Ok(Update::Progress(latest)) => progress = Some(latest),
+                Ok(Update::Done(result)) => return (progress, Some(result)),
+                Err(TryRecvError::Empty) => return (progress, None),
+                Err(TryRecvError::Disconnected) => {
+                    let error = anyhow::anyhow!("Generation stopped without a planet");
+                    return (progress, Some(Err(error)));
+                }
+            }
+        }
+    }
+}
+
+/// Generation run a stage per frame, as the web has no threads to run it
+/// on, so the page keeps responding between stages.
+#[cfg(target_arch = "wasm32")]
+struct Worker {
+    pipeline: Pipeline,
+    planet: Option<PlanetData>,
+    progress: Rc<RefCell<Option<Progress>>>,
+}
+#[cfg(target_arch = "wasm32")]
+impl Worker {
+    fn start(params: GenerationParams, cancel: CancelToken) -> Self {
+        let mut pipeline = pipeline(cancel);
+        let progress = Rc::new(RefCell::new(None));
+        let latest = progress.clone();
+        pipeline.on_progress(move |progress| *latest.borrow_mut() = Some(progress.clone()));
+        pipeline.start().expect("the standard stages always run");
+        Worker {
+            pipeline,
+            planet: Some(PlanetData::new(params)),
+            progress,
+        }
+    }
+
+    /// Run the next stage, giving the latest progress and the planet once
+    /// done.
+    fn poll(&mut self) -> (Option<Progress>, Option<Result<PlanetData>>) {
+        let planet = match &mut self.planet {
+            Some(planet) => planet,
+            None => return (None, None),
+        };
+        let result = self.pipeline.step(planet);
+        let progress = self.progress.borrow_mut().take();
+        match result {
+            Ok(true) => (progress, None),
+            Ok(false) => (progress, self.planet.take().map(Ok)),
+            Err(e) => {
+                self.planet = None;
+                (progress, Some(Err(e)))
+            }
+        }
+    }
+}
+
+/// A planet being generated while the window keeps responding, which C
+/// cancels. Once done, the arrow keys step through its drift up to the
+/// finished planet.
+struct Generation {
+    worker: Option<Worker>,
+    cancel: CancelToken,
+    planet: Option<PlanetData>,
+    // the drift frame on screen, or one past the last for the finished
+    // planet
+    frame: usize,
+    // point on the surface the mesh on screen was built around
+    origin: DVec3,
+}
+impl Generation {
+    fn new(params: GenerationParams) -> Self {
+        let cancel = CancelToken::new();
+        Generation {
+            worker: Some(Worker::start(params, cancel.clone())),
+            cancel,
+            planet: None,
+            frame: 0,
+            origin: DVec3::ZERO,
+        }
+    }
+
+    /// Show how far along generation is in the window title, then the
+    /// planet once it's done.
+    fn step(&mut self, window: &Window, engine: &mut Engine, controller: &mut CameraController) {
+        let (progress, result) = match &mut self.worker {
+            Some(worker) => worker.poll(),
+            None => return,
+        };
+        if let Some(progress) = progress {
+            window.set_title(&format!(
+                "{} - {} {:.0}%",
+                TITLE,
+                progress.stage,
+                progress.fraction * 100.0
+            ));
+        }
+        let planet = match result {
+            Some(Ok(planet)) => planet,
+            Some(Err(e)) => {
+                self.worker = None;
+                if e.is::<Cancelled>() {
+                    window.set_title(&format!("{} - cancelled", TITLE));
+                } else {
+                    log::error!("Generation failed: {:#}", e);
+                    window.set_title(&format!("{} - failed", TITLE));
+                }
+                return;
+            }
+            None => return,
+        };
+        self.worker = None;
+
+        log::info!("Generated {} cells", planet.sphere.num_cells());
+        // draw the planet with a radius of one, keeping the camera above its
+        // highest peak in any frame
+        let radius = planet.sphere.get_radius();
+        let peak = planet.get::<f32>("elevation").and_then(|e| e.max());
+        let highest = planet.timeline.crust_range().1.max(peak.unwrap_or(0.0));
+        engine.set_transform(Mat4::from_scale(Vec3::splat(1.0 / radius)));
+        controller.set_surface(1.0 + highest.max(0.0) / radius);
+        self.origin = below(controller, planet.sphere.get_radius_f64());
+        let last = planet.timeline.len();
+        self.planet = Some(planet);
+        self.show(last, window, engine);
+    }
+
+    /// Rebuild the mesh around the point below the camera once the camera
+    /// is further from where it was built than it is above the ground.
+    ///
+    /// Positions are taken relative to that point in `f64` and rounded to
+    /// `f32` after, so the terrain near the camera keeps its detail however
+    /// large the planet is.
+    fn follow(&mut self, controller: &CameraController, window: &Window, engine: &mut Engine) {
+        let radius = match &self.planet {
+            Some(planet) => planet.sphere.get_radius_f64(),
+            None => return,
+        };
+        let below = below(controller, radius);
+        let altitude = controller.get_eye().length() as f64 - 1.0;
+        if below.distance(self.origin) > altitude * radius {
+            self.origin = below;
+            self.show(self.frame, window, engine);
+        }
+    }
+
+    /// Cancel generation with C. Once done, step through the planet's
+    /// history with the arrow keys, or jump to its start and end with Home
+    /// and End.
+    fn key(&mut self, key: VirtualKeyCode, window: &Window, engine: &mut Engine) {
+        if key == VirtualKeyCode::C {
+            self.cancel.cancel();
+            return;
+        }
+        let last = match &self.planet {
+            Some(planet) => planet.timeline.len(),
+            None => return,
+        };
+        let frame = match key {
+            VirtualKeyCode::Left => self.frame.saturating_sub(1),
+            VirtualKeyCode::Right => self.frame + 1,
+            VirtualKeyCode::Home => 0,
+            VirtualKeyCode::End => last,
+            _ => return,
+        };
+        self.show(frame.min(last), window, engine);
+    }
+
+    /// Show a frame of the drift, coloured by its crust with plate
+    /// boundaries darkened, or the finished planet's elevation for the
+    /// frame after the last.
+    fn show(&mut self, frame: usize, window: &Window, engine: &mut Engine) {
+        let planet = match &self.planet {
+            Some(planet) => planet,
+            None => return,
+        };
+        let sphere = &planet.sphere;
+        let frames = planet.timeline.get_frames();
+        let (heights, colors, title) = match frames.get(frame) {
+            Some(shown) => (
+                shown.crust.get_values(),
+                shown.colors(sphere, &planet.timeline.get_ramp()),
+                format!(
+                    "{} - time {:.2}, step {} of {}",
+                    TITLE,
+                    shown.time,
+                    frame,
+                    frames.len() - 1
+                ),
+            ),
+            None => {
+                let elevation = match planet.get::<f32>("elevation") {
+                    Some(elevation) => elevation,
+                    None => return,
+                };
+                let (min, max) = (elevation.min(), elevation.max());
+                let (min, max) = (min.unwrap_or(0.0), max.unwrap_or(0.0));
+                let ramp = ColorRamp::terrain(min, 0.0, max.max(-min));
+                (
+                    elevation.get_values(),
+                    elevation.iter().map(|&e| ramp.sample(e)).collect(),
+                    format!("{} - time {:.2}, finished", TITLE, planet.time),
+                )
+            }
+        };
+        let mesh = MeshData::from_icosphere_relative(sphere, heights, self.origin)
+            .with_cell_colors(&colors);
+        engine.set_mesh(&graphics::vertices(&mesh), &mesh.indices);
+        engine.set_origin((self.origin / sphere.get_radius_f64()).as_vec3());
+        self.frame = frame;
+        window.set_title(&title);
+    }
+}
+
+// point on a sphere of `radius` below the camera
+fn below(controller: &CameraController, radius: f64) -> DVec3 {
+    controller.get_eye().as_dvec3().normalize_or_zero() * radius
+}
 
 pub async fn run(event_loop: EventLoop<()>, window: Window) {
     // Engine::new uses async code, so we're going to wait for it to finish
     let mut engine = Engine::new(&window).await;
+    let mut generation = Generation::new(GenerationParams::default());
+    let mut controller = CameraController::new();
+    let mut last_frame = Instant::now();
 
-    let time = std::time::Instant::now();
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
             } if window_id == window.id() => {
+                controller.process_event(event);
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -29,6 +315,15 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                             },
                         ..
                     } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => generation.key(*key, &window, &mut engine),
                     WindowEvent::Resized(physical_size) => {
                         let size = *physical_size;
                         engine.resize(size.width, size.height);
@@ -42,7 +337,11 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                 }
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                engine.update(time.elapsed().as_secs_f32());
+                let now = Instant::now();
+                controller.update((now - last_frame).as_secs_f32());
+                last_frame = now;
+                generation.follow(&controller, &window, &mut engine);
+                engine.update_camera(&controller);
                 match engine.render() {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
@@ -56,6 +355,7 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                 }
             }
             Event::MainEventsCleared => {
+                generation.step(&window, &mut engine, &mut controller);
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
                 window.request_redraw();
@@ -0,0 +1,184 @@
+//! Generation must give bit-identical planets for a seed on every platform,
+//! wasm32 included, so these hashes only change along with the generator.
+
+use planetgen::{
+    generate::{generate, standard_pipeline, Checkpoints, Drift, Erosion, Noise},
+    planet::{GenerationParams, PlanetData},
+    rng::Rng,
+    save::{load_planet, write_planet},
+    vector::{contours, plate_boundaries},
+};
+
+fn small(seed: u64) -> GenerationParams {
+    GenerationParams {
+        seed,
+        subdivisions: 3,
+        plates: 8,
+        ..Default::default()
+    }
+}
+
+// FNV-1a over the planet's save file, which covers the mesh, the plates and
+// every field bit for bit
+fn hash(planet: &PlanetData) -> u64 {
+    let mut bytes = Vec::new();
+    write_planet(planet, &mut bytes).unwrap();
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[test]
+fn golden_hashes() {
+    let golden = [
+        (0, 0xaeda_96c1_9227_46db),
+        (1, 0x766a_2993_f7a4_8d03),
+        (42, 0x57d4_3f98_8678_f512),
+    ];
+    for (seed, expected) in golden {
+        let planet = generate(&small(seed));
+        assert_eq!(
+            hash(&planet),
+            expected,
+            "seed {}: {:#018x}",
+            seed,
+            hash(&planet)
+        );
+    }
+}
+
+#[test]
+fn stages_draw_from_their_own_streams() {
+    let planet = generate(&small(7));
+
+    let mut pipeline = standard_pipeline();
+    pipeline
+        .replace(
+            "erosion",
+            Erosion {
+                iterations: 3,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    pipeline
+        .replace(
+            "noise",
+            Noise {
+                octaves: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let mut changed = PlanetData::new(small(7));
+    pipeline.run(&mut changed).unwrap();
+
+    assert_eq!(changed.get_ids("plate"), planet.get_ids("plate"));
+    assert_eq!(changed.get_scalar("crust"), planet.get_scalar("crust"));
+    assert_eq!(changed.plates, planet.plates);
+    assert_ne!(
+        changed.get_scalar("elevation"),
+        planet.get_scalar("elevation")
+    );
+}
+
+#[test]
+fn streams_differ_by_name_and_seed() {
+    let first = |mut rng: Rng| rng.next_u64();
+    let plates = first(Rng::stream(1, "plates"));
+    assert_eq!(plates, first(Rng::stream(1, "plates")));
+    assert_ne!(plates, first(Rng::stream(1, "noise")));
+    assert_ne!(plates, first(Rng::stream(2, "plates")));
+}
+
+#[test]
+fn resuming_from_checkpoints_matches_an_uninterrupted_run() {
+    let params = GenerationParams {
+        subdivisions: 4,
+        ..small(3)
+    };
+    let planet = generate(&params);
+
+    let dir = std::env::temp_dir().join(format!("planetgen-checkpoints-{}", std::process::id()));
+    let checkpoints = Checkpoints::new(&dir, 5);
+    let recording = || {
+        let mut pipeline = standard_pipeline();
+        let drift = Drift {
+            record_every: 1,
+            checkpoints: Some(checkpoints.clone()),
+            ..Default::default()
+        };
+        pipeline.replace("drift", drift).unwrap();
+        pipeline
+    };
+    let mut saving = PlanetData::new(params);
+    recording().run(&mut saving).unwrap();
+    assert_eq!(
+        saving.get_scalar("elevation"),
+        planet.get_scalar("elevation")
+    );
+    assert_eq!(saving.timeline.len(), 21);
+    assert_eq!(
+        checkpoints.latest().unwrap(),
+        Some(checkpoints.get_path(15))
+    );
+
+    for step in [5, 10, 15] {
+        let mut resumed = load_planet(checkpoints.get_path(step)).unwrap();
+        recording().run_from(&mut resumed, "drift").unwrap();
+        assert_eq!(hash(&resumed), hash(&saving), "resumed after step {}", step);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn vector_features_come_out_in_a_fixed_order() {
+    let planet = generate(&small(5));
+    let elevation = planet.get_scalar("elevation").unwrap();
+    let plates = planet.get_ids("plate").unwrap();
+    let velocities = planet.get_vectors("velocity");
+
+    let first_contours = contours(&planet.sphere, elevation, 0.0);
+    let first_boundaries = plate_boundaries(&planet.sphere, plates, velocities);
+    // every map is seeded differently, so a few tries catch a dependence
+    // on iteration order
+    for _ in 0..4 {
+        let lines = contours(&planet.sphere, elevation, 0.0);
+        assert_eq!(lines.len(), first_contours.len());
+        for (line, first) in lines.iter().zip(&first_contours) {
+            assert_eq!(line.points, first.points);
+        }
+        let boundaries = plate_boundaries(&planet.sphere, plates, velocities);
+        assert_eq!(boundaries.len(), first_boundaries.len());
+        for (boundary, first) in boundaries.iter().zip(&first_boundaries) {
+            assert_eq!(boundary.plates, first.plates);
+            assert_eq!(boundary.kind, first.kind);
+            assert_eq!(boundary.line.points, first.line.points);
+        }
+    }
+}
+
+#[test]
+fn rerunning_the_drift_matches_a_fresh_run() {
+    let params = small(4);
+    let mut pipeline = standard_pipeline();
+    let mut planet = PlanetData::new(params);
+    pipeline.run(&mut planet).unwrap();
+    let first = hash(&planet);
+    pipeline.run_from(&mut planet, "drift").unwrap();
+    assert_eq!(hash(&planet), first);
+
+    let longer = || Drift {
+        steps: 30,
+        ..Default::default()
+    };
+    pipeline.replace("drift", longer()).unwrap();
+    pipeline.run_from(&mut planet, "drift").unwrap();
+    let mut fresh = PlanetData::new(params);
+    let mut fresh_pipeline = standard_pipeline();
+    fresh_pipeline.replace("drift", longer()).unwrap();
+    fresh_pipeline.run(&mut fresh).unwrap();
+    assert_eq!(planet.time, fresh.time);
+    assert_eq!(hash(&planet), hash(&fresh));
+    assert_ne!(hash(&planet), first);
+}